@@ -0,0 +1,3 @@
+DROP TRIGGER goals_record_stage_change;
+DROP TRIGGER goals_record_initial_stage;
+DROP TABLE goal_stage_transitions;
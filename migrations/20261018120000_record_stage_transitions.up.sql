@@ -0,0 +1,30 @@
+CREATE TABLE goal_stage_transitions (
+	id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+	goal_id INTEGER NOT NULL,
+	from_stage INTEGER,
+	to_stage INTEGER NOT NULL,
+	-- Set for the rows made below, whose time is when tracking began rather
+	-- than when the goal reached the stage
+	backfilled INTEGER DEFAULT 0 NOT NULL,
+	created_at TEXT DEFAULT CURRENT_TIMESTAMP NOT NULL,
+	FOREIGN KEY (goal_id) REFERENCES goals(id) ON DELETE CASCADE
+);
+
+CREATE INDEX "goal_stage_transitions_goal_id" ON goal_stage_transitions(goal_id);
+
+-- We don't know when existing goals reached their stage, so start tracking from now
+INSERT INTO goal_stage_transitions(goal_id, from_stage, to_stage, backfilled)
+SELECT id, NULL, stage, 1 FROM goals;
+
+CREATE TRIGGER goals_record_initial_stage AFTER INSERT ON goals
+BEGIN
+	INSERT INTO goal_stage_transitions(goal_id, from_stage, to_stage)
+	VALUES (NEW.id, NULL, NEW.stage);
+END;
+
+CREATE TRIGGER goals_record_stage_change AFTER UPDATE OF stage ON goals
+WHEN OLD.stage != NEW.stage
+BEGIN
+	INSERT INTO goal_stage_transitions(goal_id, from_stage, to_stage)
+	VALUES (NEW.id, OLD.stage, NEW.stage);
+END;
@@ -0,0 +1,8 @@
+DROP TRIGGER groups_search_update;
+DROP TRIGGER groups_search_delete;
+DROP TRIGGER groups_search_insert;
+DROP TRIGGER goals_search_update;
+DROP TRIGGER goals_search_delete;
+DROP TRIGGER goals_search_insert;
+DROP TABLE groups_search;
+DROP TABLE goals_search;
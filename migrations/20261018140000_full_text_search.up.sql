@@ -0,0 +1,50 @@
+CREATE VIRTUAL TABLE goals_search USING fts5(
+	title,
+	description,
+	content='goals',
+	content_rowid='id'
+);
+CREATE VIRTUAL TABLE groups_search USING fts5(
+	title,
+	description,
+	content='groups',
+	content_rowid='id'
+);
+INSERT INTO goals_search(goals_search) VALUES('rebuild');
+INSERT INTO groups_search(groups_search) VALUES('rebuild');
+
+CREATE TRIGGER goals_search_insert AFTER INSERT ON goals
+BEGIN
+	INSERT INTO goals_search(rowid, title, description)
+	VALUES (NEW.id, NEW.title, NEW.description);
+END;
+CREATE TRIGGER goals_search_delete AFTER DELETE ON goals
+BEGIN
+	INSERT INTO goals_search(goals_search, rowid, title, description)
+	VALUES ('delete', OLD.id, OLD.title, OLD.description);
+END;
+CREATE TRIGGER goals_search_update AFTER UPDATE OF title, description ON goals
+BEGIN
+	INSERT INTO goals_search(goals_search, rowid, title, description)
+	VALUES ('delete', OLD.id, OLD.title, OLD.description);
+	INSERT INTO goals_search(rowid, title, description)
+	VALUES (NEW.id, NEW.title, NEW.description);
+END;
+
+CREATE TRIGGER groups_search_insert AFTER INSERT ON groups
+BEGIN
+	INSERT INTO groups_search(rowid, title, description)
+	VALUES (NEW.id, NEW.title, NEW.description);
+END;
+CREATE TRIGGER groups_search_delete AFTER DELETE ON groups
+BEGIN
+	INSERT INTO groups_search(groups_search, rowid, title, description)
+	VALUES ('delete', OLD.id, OLD.title, OLD.description);
+END;
+CREATE TRIGGER groups_search_update AFTER UPDATE OF title, description ON groups
+BEGIN
+	INSERT INTO groups_search(groups_search, rowid, title, description)
+	VALUES ('delete', OLD.id, OLD.title, OLD.description);
+	INSERT INTO groups_search(rowid, title, description)
+	VALUES (NEW.id, NEW.title, NEW.description);
+END;
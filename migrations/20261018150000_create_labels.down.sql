@@ -0,0 +1,2 @@
+DROP TABLE goal_labels;
+DROP TABLE labels;
@@ -0,0 +1,16 @@
+CREATE TABLE labels (
+	id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+	name TEXT NOT NULL,
+	color TEXT CHECK(color IN ('gray', 'red', 'orange', 'amber', 'green', 'teal', 'sky', 'blue', 'violet', 'pink')) NOT NULL DEFAULT 'gray',
+	user_id INTEGER NOT NULL,
+	FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+	UNIQUE (user_id, name)
+);
+CREATE TABLE goal_labels (
+	goal_id INTEGER NOT NULL,
+	label_id INTEGER NOT NULL,
+	PRIMARY KEY (goal_id, label_id),
+	FOREIGN KEY (goal_id) REFERENCES goals(id) ON DELETE CASCADE,
+	FOREIGN KEY (label_id) REFERENCES labels(id) ON DELETE CASCADE
+);
+CREATE INDEX "goal_labels_label_id" ON goal_labels(label_id);
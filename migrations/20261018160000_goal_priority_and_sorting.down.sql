@@ -0,0 +1,6 @@
+DROP TABLE group_goal_sorts;
+DROP TRIGGER goals_set_updated_at_update;
+DROP TRIGGER goals_set_updated_at_insert;
+ALTER TABLE goals DROP COLUMN position;
+ALTER TABLE goals DROP COLUMN updated_at;
+ALTER TABLE goals DROP COLUMN priority;
@@ -0,0 +1,29 @@
+ALTER TABLE goals ADD COLUMN priority INTEGER NOT NULL DEFAULT 1 CHECK (priority BETWEEN 0 AND 3);
+ALTER TABLE goals ADD COLUMN updated_at TEXT NOT NULL DEFAULT '1970-01-01 00:00:00';
+ALTER TABLE goals ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+
+UPDATE goals SET position = id;
+
+UPDATE goals SET updated_at = COALESCE(
+	(SELECT MAX(created_at) FROM goal_stage_transitions WHERE goal_id = goals.id),
+	CURRENT_TIMESTAMP
+);
+
+CREATE TRIGGER goals_set_updated_at_insert AFTER INSERT ON goals
+BEGIN
+	UPDATE goals SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER goals_set_updated_at_update AFTER UPDATE OF title, description, stage, group_id, deadline, priority ON goals
+BEGIN
+	UPDATE goals SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE TABLE group_goal_sorts (
+	user_id INTEGER NOT NULL,
+	group_id INTEGER NOT NULL,
+	sort TEXT NOT NULL CHECK (sort IN ('manual', 'priority', 'deadline', 'updated')),
+	PRIMARY KEY (user_id, group_id),
+	FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+	FOREIGN KEY (group_id) REFERENCES groups(id) ON DELETE CASCADE
+);
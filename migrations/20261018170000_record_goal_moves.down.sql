@@ -0,0 +1,2 @@
+DROP TRIGGER goals_record_group_change;
+DROP TABLE goal_moves;
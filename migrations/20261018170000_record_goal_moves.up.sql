@@ -0,0 +1,21 @@
+CREATE TABLE goal_moves (
+	id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+	goal_id INTEGER NOT NULL,
+	from_group_id INTEGER,
+	to_group_id INTEGER,
+	from_stage INTEGER NOT NULL,
+	to_stage INTEGER NOT NULL,
+	created_at TEXT DEFAULT CURRENT_TIMESTAMP NOT NULL,
+	FOREIGN KEY (goal_id) REFERENCES goals(id) ON DELETE CASCADE,
+	FOREIGN KEY (from_group_id) REFERENCES groups(id) ON DELETE SET NULL,
+	FOREIGN KEY (to_group_id) REFERENCES groups(id) ON DELETE SET NULL
+);
+
+CREATE INDEX "goal_moves_goal_id" ON goal_moves(goal_id);
+
+CREATE TRIGGER goals_record_group_change AFTER UPDATE OF group_id ON goals
+WHEN OLD.group_id != NEW.group_id
+BEGIN
+	INSERT INTO goal_moves(goal_id, from_group_id, to_group_id, from_stage, to_stage)
+	VALUES (NEW.id, OLD.group_id, NEW.group_id, OLD.stage, NEW.stage);
+END;
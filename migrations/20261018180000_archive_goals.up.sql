@@ -0,0 +1 @@
+ALTER TABLE goals ADD COLUMN archived_at TEXT;
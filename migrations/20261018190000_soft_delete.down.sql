@@ -0,0 +1,4 @@
+DROP INDEX "groups_deleted_at";
+DROP INDEX "goals_deleted_at";
+ALTER TABLE groups DROP COLUMN deleted_at;
+ALTER TABLE goals DROP COLUMN deleted_at;
@@ -0,0 +1,5 @@
+ALTER TABLE goals ADD COLUMN deleted_at TEXT;
+ALTER TABLE groups ADD COLUMN deleted_at TEXT;
+
+CREATE INDEX "goals_deleted_at" ON goals(deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX "groups_deleted_at" ON groups(deleted_at) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE groups DROP COLUMN color;
+ALTER TABLE groups DROP COLUMN icon;
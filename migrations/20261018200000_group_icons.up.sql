@@ -0,0 +1,4 @@
+-- Either the name of a built-in icon or an emoji
+ALTER TABLE groups ADD COLUMN icon TEXT;
+-- One of the label colours
+ALTER TABLE groups ADD COLUMN color TEXT;
@@ -0,0 +1,2 @@
+DROP TABLE group_members;
+ALTER TABLE groups DROP COLUMN archived_at;
@@ -0,0 +1,20 @@
+-- Archived groups are kept but hidden and can't be changed
+ALTER TABLE groups ADD COLUMN archived_at TEXT;
+
+-- Where each of a user's groups sits in their sidebar and on their dashboard,
+-- pinned groups always come first. It's kept per member rather than on the
+-- group, so everyone a group is shared with can arrange their own.
+CREATE TABLE group_members (
+	group_id INTEGER NOT NULL,
+	user_id INTEGER NOT NULL,
+	position INTEGER NOT NULL DEFAULT 0,
+	pinned BOOLEAN NOT NULL DEFAULT FALSE,
+	created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	PRIMARY KEY (group_id, user_id),
+	FOREIGN KEY (group_id) REFERENCES groups(id) ON DELETE CASCADE,
+	FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX "group_members_user_position" ON group_members(user_id, pinned, position);
+
+INSERT INTO group_members(group_id, user_id, position)
+SELECT id, user_id, id FROM groups;
@@ -0,0 +1,7 @@
+ALTER TABLE goals DROP COLUMN moved_at;
+ALTER TABLE goals DROP COLUMN moved_by;
+ALTER TABLE goals DROP COLUMN created_by;
+
+DROP TABLE group_invitations;
+
+ALTER TABLE group_members DROP COLUMN role;
@@ -0,0 +1,25 @@
+-- Groups can be shared, and everyone who can see one is a member of it. The
+-- role is one of owner, editor or viewer, and until now every member was the
+-- group's owner.
+ALTER TABLE group_members ADD COLUMN role TEXT NOT NULL DEFAULT 'viewer';
+UPDATE group_members SET role = 'owner';
+
+CREATE TABLE group_invitations (
+	id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+	group_id INTEGER NOT NULL,
+	email TEXT NOT NULL,
+	role TEXT NOT NULL DEFAULT 'viewer',
+	-- Only a hash of the emailed token is kept, like login links
+	token_hash TEXT NOT NULL UNIQUE,
+	invited_by INTEGER NOT NULL,
+	created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	FOREIGN KEY (group_id) REFERENCES groups(id) ON DELETE CASCADE,
+	FOREIGN KEY (invited_by) REFERENCES users(id) ON DELETE CASCADE,
+	UNIQUE (group_id, email)
+);
+
+ALTER TABLE goals ADD COLUMN created_by INTEGER REFERENCES users(id) ON DELETE SET NULL;
+ALTER TABLE goals ADD COLUMN moved_by INTEGER REFERENCES users(id) ON DELETE SET NULL;
+ALTER TABLE goals ADD COLUMN moved_at TEXT;
+
+UPDATE goals SET created_by = (SELECT user_id FROM groups WHERE groups.id = goals.group_id);
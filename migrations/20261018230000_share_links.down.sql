@@ -0,0 +1,2 @@
+DROP INDEX "share_links_group";
+DROP TABLE share_links;
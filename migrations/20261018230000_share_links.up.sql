@@ -0,0 +1,12 @@
+-- Read only links to a group for people without an account. The token is the
+-- only thing needed to see the group, so links can expire and be revoked.
+CREATE TABLE share_links (
+	id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+	group_id INTEGER NOT NULL,
+	token TEXT NOT NULL UNIQUE,
+	hide_descriptions BOOLEAN NOT NULL DEFAULT FALSE,
+	expires_at TEXT,
+	created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	FOREIGN KEY (group_id) REFERENCES groups(id) ON DELETE CASCADE
+);
+CREATE INDEX "share_links_group" ON share_links(group_id);
@@ -0,0 +1,2 @@
+DROP INDEX "login_sessions_user";
+DROP TABLE login_sessions;
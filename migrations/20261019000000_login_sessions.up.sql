@@ -0,0 +1,14 @@
+-- One row for every place a user is logged in. The session id is part of the
+-- identity cookie, and a request whose session has no row here is logged out,
+-- so deleting a row signs that device out on its next request.
+CREATE TABLE login_sessions (
+	id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+	user_id INTEGER NOT NULL,
+	session_id TEXT NOT NULL UNIQUE,
+	method TEXT NOT NULL DEFAULT 'code',
+	user_agent TEXT NOT NULL DEFAULT '',
+	created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	last_seen_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX "login_sessions_user" ON login_sessions(user_id);
@@ -0,0 +1,13 @@
+-- Devices a user has logged in from before. A device is the long lived device
+-- cookie together with a summary of the user agent, and logging in from one
+-- that isn't here sends the user a security email.
+CREATE TABLE known_devices (
+	id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+	user_id INTEGER NOT NULL,
+	device_id TEXT NOT NULL,
+	user_agent TEXT NOT NULL,
+	created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	last_seen_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+	UNIQUE (user_id, device_id, user_agent)
+);
@@ -0,0 +1,2 @@
+DROP INDEX "webauthn_credentials_credential_id";
+ALTER TABLE webauthn_credentials DROP COLUMN credential_id;
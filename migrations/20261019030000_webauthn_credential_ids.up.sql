@@ -0,0 +1,6 @@
+-- The authenticator's own id for each passkey, so a passkey login can find
+-- its user without being told their email first. Encoded the same way the
+-- passkey json stores it.
+ALTER TABLE webauthn_credentials ADD COLUMN credential_id TEXT;
+UPDATE webauthn_credentials SET credential_id = json_extract(passkey, '$.cred.cred_id');
+CREATE INDEX "webauthn_credentials_credential_id" ON webauthn_credentials(credential_id);
@@ -0,0 +1,4 @@
+-- When the user last proved who they are in each login session, either by
+-- logging in or by confirming again before a sensitive change
+ALTER TABLE login_sessions ADD COLUMN authenticated_at TEXT;
+UPDATE login_sessions SET authenticated_at = created_at;
@@ -0,0 +1,5 @@
+DROP TRIGGER users_delete_tones;
+DROP INDEX "users_restore_token_hash";
+DROP INDEX "users_deletion_scheduled_for";
+ALTER TABLE users DROP COLUMN restore_token_hash;
+ALTER TABLE users DROP COLUMN deletion_scheduled_for;
@@ -0,0 +1,14 @@
+-- Deleted accounts are locked and kept until deletion_scheduled_for, so they
+-- can be restored with the link emailed when deletion was asked for
+ALTER TABLE users ADD COLUMN deletion_scheduled_for TEXT;
+ALTER TABLE users ADD COLUMN restore_token_hash TEXT;
+CREATE INDEX "users_deletion_scheduled_for" ON users(deletion_scheduled_for) WHERE deletion_scheduled_for IS NOT NULL;
+CREATE UNIQUE INDEX "users_restore_token_hash" ON users(restore_token_hash);
+
+-- tones.user_id has no ON DELETE CASCADE, and rebuilding the table would
+-- cascade into every group using a tone. A user's own tones go with them here
+-- instead, so deleting the user isn't stopped by the foreign key.
+CREATE TRIGGER users_delete_tones BEFORE DELETE ON users
+BEGIN
+	DELETE FROM tones WHERE user_id = OLD.id AND global = 0;
+END;
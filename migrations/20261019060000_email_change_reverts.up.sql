@@ -0,0 +1,14 @@
+-- Links emailed to the old address after an email change, so the owner can
+-- undo a change they didn't make. Only a hash of the token is kept, and a
+-- link can be used once before it expires.
+CREATE TABLE email_change_reverts (
+	id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+	user_id INTEGER NOT NULL,
+	old_email TEXT NOT NULL,
+	new_email TEXT NOT NULL,
+	token_hash TEXT NOT NULL UNIQUE,
+	expires_at TEXT NOT NULL,
+	used_at TEXT,
+	created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,4 @@
+DROP INDEX "email_sends_blocked";
+DROP INDEX "email_sends_ip";
+DROP INDEX "email_sends_recipient";
+DROP TABLE email_sends;
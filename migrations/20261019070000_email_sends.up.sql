@@ -0,0 +1,14 @@
+-- Every email sent because someone filled in a public form, used to rate
+-- limit them by recipient and by ip address. Attempts that were turned away
+-- are kept too, with blocked set, so operators can see them.
+CREATE TABLE email_sends (
+	id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+	kind TEXT NOT NULL,
+	recipient TEXT NOT NULL,
+	ip TEXT,
+	blocked INTEGER NOT NULL DEFAULT 0,
+	created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX "email_sends_recipient" ON email_sends(recipient, created_at);
+CREATE INDEX "email_sends_ip" ON email_sends(ip, created_at);
+CREATE INDEX "email_sends_blocked" ON email_sends(created_at) WHERE blocked = 1;
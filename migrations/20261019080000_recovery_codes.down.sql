@@ -0,0 +1,2 @@
+DROP INDEX "recovery_codes_user_id";
+DROP TABLE recovery_codes;
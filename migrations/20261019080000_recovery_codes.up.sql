@@ -0,0 +1,11 @@
+-- One-time codes for getting back into an account after losing both the
+-- email inbox and every passkey. Only a hash of each code is kept.
+CREATE TABLE recovery_codes (
+	id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+	user_id INTEGER NOT NULL,
+	code_hash TEXT NOT NULL UNIQUE,
+	used_at TEXT,
+	created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX "recovery_codes_user_id" ON recovery_codes(user_id);
@@ -0,0 +1,4 @@
+DROP TRIGGER "security_events_no_delete";
+DROP TRIGGER "security_events_no_update";
+DROP INDEX "security_events_user_id";
+DROP TABLE security_events;
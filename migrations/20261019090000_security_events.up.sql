@@ -0,0 +1,25 @@
+-- A record of everything that touched the security of an account, shown to
+-- its owner. Events are only ever added. They go when the account does.
+CREATE TABLE security_events (
+	id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+	user_id INTEGER NOT NULL,
+	kind TEXT NOT NULL,
+	detail TEXT,
+	ip TEXT,
+	user_agent TEXT,
+	created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX "security_events_user_id" ON security_events(user_id, created_at);
+
+CREATE TRIGGER "security_events_no_update" BEFORE UPDATE ON security_events
+BEGIN
+	SELECT RAISE(ABORT, 'security events can not be changed');
+END;
+
+-- Deleting the account cascades here after the user is gone, which is allowed
+CREATE TRIGGER "security_events_no_delete" BEFORE DELETE ON security_events
+WHEN EXISTS (SELECT 1 FROM users WHERE id = OLD.user_id)
+BEGIN
+	SELECT RAISE(ABORT, 'security events can not be deleted');
+END;
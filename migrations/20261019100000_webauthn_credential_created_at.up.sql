@@ -0,0 +1,4 @@
+-- When each passkey was added, so undoing an email change can remove the ones
+-- added after it. SQLite can't add a column defaulting to the current time,
+-- so new passkeys set it themselves and older ones are left without one.
+ALTER TABLE webauthn_credentials ADD COLUMN created_at TEXT;
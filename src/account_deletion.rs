@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use actix_web::error::ErrorInternalServerError;
+use base64::{engine::general_purpose, Engine};
+use log::{error, info};
+use rand::{thread_rng, Rng};
+use sqlx::{pool::PoolConnection, Sqlite, SqlitePool};
+
+use crate::login_links::hash_token;
+
+const DEFAULT_GRACE_DAYS: i64 = 14;
+
+/// How many days a deleted account stays locked, and can still be restored,
+/// before it is removed for good. Set with `ACCOUNT_DELETION_GRACE_DAYS`.
+#[derive(Clone, Copy, Debug)]
+pub struct DeletionGracePeriod(pub i64);
+
+impl DeletionGracePeriod {
+    pub fn from_env() -> Self {
+        let days = dotenvy::var("ACCOUNT_DELETION_GRACE_DAYS")
+            .ok()
+            .and_then(|days| days.parse().ok())
+            .filter(|days| *days > 0)
+            .unwrap_or(DEFAULT_GRACE_DAYS);
+        Self(days)
+    }
+}
+
+impl Default for DeletionGracePeriod {
+    fn default() -> Self {
+        Self(DEFAULT_GRACE_DAYS)
+    }
+}
+
+/// Give a locked account a new restore link, replacing any older one, and
+/// return its url
+pub async fn new_restore_link(
+    conn: &mut PoolConnection<Sqlite>,
+    hostname: &str,
+    user_id: i64,
+) -> actix_web::Result<String> {
+    let mut token_bytes = [0u8; 32];
+    thread_rng().fill(&mut token_bytes);
+    let token = general_purpose::URL_SAFE_NO_PAD.encode(token_bytes);
+    let token_hash = hash_token(&token);
+
+    sqlx::query!(
+        "UPDATE users SET restore_token_hash = $1 WHERE id = $2;",
+        token_hash,
+        user_id
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(format!("https://{hostname}/account/restore/{token}"))
+}
+
+/// Remove accounts whose grace period is over, returning how many were
+/// removed. Their groups, goals, credentials and sessions cascade with them,
+/// and a trigger takes their own tones. Each account is deleted on its own, so
+/// one that can't be doesn't hold up the rest.
+pub async fn purge_deleted_accounts(pool: &SqlitePool) -> sqlx::Result<u64> {
+    let user_ids = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE deletion_scheduled_for <= CURRENT_TIMESTAMP;"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut purged = 0;
+    for user_id in user_ids {
+        match sqlx::query!("DELETE FROM users WHERE id = $1;", user_id)
+            .execute(pool)
+            .await
+        {
+            Ok(result) => purged += result.rows_affected(),
+            Err(err) => error!("Could not delete account {}: {}", user_id, err),
+        }
+    }
+    Ok(purged)
+}
+
+/// Remove accounts that are past their grace period once an hour, forever
+pub async fn purge_periodically(pool: SqlitePool) {
+    let mut interval = actix_web::rt::time::interval(Duration::from_secs(60 * 60));
+    loop {
+        interval.tick().await;
+        match purge_deleted_accounts(&pool).await {
+            Ok(0) => {}
+            Ok(purged) => info!("Deleted {} accounts for good", purged),
+            Err(err) => error!("Could not delete accounts: {}", err),
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::fmt;
+
+use chrono::{Datelike, Duration, Months, NaiveDate};
+use serde::Deserialize;
+
+use crate::GoalWithGroup;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CalendarView {
+    #[default]
+    Month,
+    Week,
+}
+
+impl fmt::Display for CalendarView {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalendarView::Month => write!(f, "month"),
+            CalendarView::Week => write!(f, "week"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CalendarDay {
+    pub date: NaiveDate,
+    /// False for the padding days before and after the month being shown
+    pub in_range: bool,
+    pub is_today: bool,
+    pub goals: Vec<GoalWithGroup>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Calendar {
+    pub view: CalendarView,
+    pub title: String,
+    pub date: NaiveDate,
+    pub previous: NaiveDate,
+    pub next: NaiveDate,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub weeks: Vec<Vec<CalendarDay>>,
+    /// Only goals from this group are shown when set
+    pub group: Option<i64>,
+    /// Only goals with this label are shown when set
+    pub label: Option<i64>,
+}
+
+fn week_start(date: NaiveDate) -> Option<NaiveDate> {
+    date.checked_sub_signed(Duration::days(date.weekday().num_days_from_monday() as i64))
+}
+
+impl Calendar {
+    /// Lay out the weeks around `date`. Weeks always start on Monday, so a
+    /// month view includes the ends of the neighbouring months. Returns None
+    /// when the calendar would run past the dates chrono can represent.
+    pub fn new(
+        view: CalendarView,
+        date: NaiveDate,
+        today: NaiveDate,
+        group: Option<i64>,
+        label: Option<i64>,
+    ) -> Option<Self> {
+        let (title, first, last, previous, next) = match view {
+            CalendarView::Month => {
+                let first = date.with_day(1).unwrap_or(date);
+                let next = first.checked_add_months(Months::new(1))?;
+                (
+                    first.format("%B %Y").to_string(),
+                    first,
+                    next.checked_sub_signed(Duration::days(1))?,
+                    first.checked_sub_months(Months::new(1))?,
+                    next,
+                )
+            }
+            CalendarView::Week => {
+                let first = week_start(date)?;
+                (
+                    first.format("Week of %B %-d, %Y").to_string(),
+                    first,
+                    first.checked_add_signed(Duration::days(6))?,
+                    first.checked_sub_signed(Duration::days(7))?,
+                    first.checked_add_signed(Duration::days(7))?,
+                )
+            }
+        };
+
+        let start = week_start(first)?;
+        let end = week_start(last)?.checked_add_signed(Duration::days(6))?;
+
+        let weeks = start
+            .iter_days()
+            .take_while(|d| *d <= end)
+            .map(|d| CalendarDay {
+                date: d,
+                in_range: d >= first && d <= last,
+                is_today: d == today,
+                goals: vec![],
+            })
+            .collect::<Vec<CalendarDay>>()
+            .chunks(7)
+            .map(|w| w.to_vec())
+            .collect();
+
+        Some(Self {
+            view,
+            title,
+            date,
+            previous,
+            next,
+            start,
+            end,
+            weeks,
+            group,
+            label,
+        })
+    }
+
+    /// Link to this calendar, keeping the view and filters, around `date`
+    pub fn url(&self, date: &NaiveDate) -> String {
+        format!("{}&date={}", self.today_url(), date)
+    }
+
+    /// Link to this calendar, keeping the view and filters, around today
+    pub fn today_url(&self) -> String {
+        let mut url = format!("/calendar?view={}", self.view);
+        if let Some(group) = self.group {
+            url.push_str(&format!("&group={}", group));
+        }
+        if let Some(label) = self.label {
+            url.push_str(&format!("&label={}", label));
+        }
+        url
+    }
+
+    pub fn is_filtered_to(&self, group_id: &i64) -> bool {
+        self.group == Some(*group_id)
+    }
+
+    pub fn is_label_filtered_to(&self, label_id: &i64) -> bool {
+        self.label == Some(*label_id)
+    }
+
+    /// Place goals on the day matching their deadline. Goals outside the
+    /// calendar or without the selected label are ignored.
+    pub fn add_goals(&mut self, goals: Vec<GoalWithGroup>) {
+        for goal in goals {
+            if self.label.is_some_and(|l| !goal.has_label(&l)) {
+                continue;
+            }
+            let Ok(deadline) = NaiveDate::parse_from_str(&goal.deadline, "%Y-%m-%d") else {
+                continue;
+            };
+            if let Some(day) = self
+                .weeks
+                .iter_mut()
+                .flatten()
+                .find(|day| day.date == deadline)
+            {
+                day.goals.push(goal);
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::net::IpAddr;
+
+use actix_web::{web, HttpRequest};
+
+/// Reverse proxies whose `X-Forwarded-For` header can be believed, set with
+/// `TRUSTED_PROXIES` as a comma separated list of ip addresses. Anyone else
+/// could put whatever they like in it.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+impl TrustedProxies {
+    pub fn from_env() -> Self {
+        Self(
+            dotenvy::var("TRUSTED_PROXIES")
+                .map(|proxies| {
+                    proxies
+                        .split(',')
+                        .filter_map(|proxy| proxy.trim().parse().ok())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        )
+    }
+}
+
+/// The ip address a request came from. Forwarded addresses are only used when
+/// the request came through a trusted proxy, and then only the last one the
+/// proxies didn't add themselves, since anything before it came from the client.
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    let peer = req.peer_addr()?.ip();
+    let Some(trusted) = req.app_data::<web::Data<TrustedProxies>>() else {
+        return Some(peer.to_string());
+    };
+    if !trusted.0.contains(&peer) {
+        return Some(peer.to_string());
+    }
+
+    let forwarded = req
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(','))
+        .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<_>>();
+    let client = forwarded
+        .into_iter()
+        .rev()
+        .find(|ip| !trusted.0.contains(ip))
+        .unwrap_or(peer);
+    Some(client.to_string())
+}
@@ -0,0 +1,149 @@
+use actix_web::{
+    cookie::{time, Cookie, SameSite},
+    error::ErrorInternalServerError,
+    web, HttpRequest,
+};
+use base64::{engine::general_purpose, Engine};
+use chrono::Utc;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use log::error;
+use rand::{thread_rng, Rng};
+use sqlx::{pool::PoolConnection, Sqlite};
+
+use crate::{
+    client_ip::client_ip,
+    login_sessions::{summarize_user_agent, LoginMethod},
+    mail::build_email_for_user,
+    User,
+};
+
+/// The cookie that tells browsers apart between logins
+pub const DEVICE_COOKIE: &str = "device_id";
+
+/// Browsers won't keep a cookie for longer than this anyway
+const DEVICE_COOKIE_DAYS: i64 = 400;
+
+/// The browser a request came from, as far as we can tell
+#[derive(Clone, Debug)]
+pub struct Device {
+    pub device_id: String,
+    pub user_agent: String,
+    pub ip: Option<String>,
+    /// Set when the browser didn't send a device cookie, and has to be given one
+    pub new_cookie: Option<Cookie<'static>>,
+}
+
+impl Device {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let user_agent = req
+            .headers()
+            .get("User-Agent")
+            .and_then(|agent| agent.to_str().ok())
+            .map(summarize_user_agent)
+            .unwrap_or_else(|| "Unknown device".into());
+        let ip = client_ip(req);
+
+        match req.cookie(DEVICE_COOKIE) {
+            Some(cookie) => Self {
+                device_id: cookie.value().to_owned(),
+                user_agent,
+                ip,
+                new_cookie: None,
+            },
+            None => {
+                let mut id_bytes = [0u8; 32];
+                thread_rng().fill(&mut id_bytes);
+                let device_id = general_purpose::URL_SAFE_NO_PAD.encode(id_bytes);
+                let cookie = Cookie::build(DEVICE_COOKIE, device_id.clone())
+                    .path("/")
+                    .secure(true)
+                    .http_only(true)
+                    .same_site(SameSite::Lax)
+                    .max_age(time::Duration::days(DEVICE_COOKIE_DAYS))
+                    .finish();
+                Self {
+                    device_id,
+                    user_agent,
+                    ip,
+                    new_cookie: Some(cookie),
+                }
+            }
+        }
+    }
+}
+
+/// Remember that the user logged in from this device. Returns true if they
+/// haven't used it before.
+pub async fn remember_device(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+    device: &Device,
+) -> actix_web::Result<bool> {
+    let inserted = sqlx::query!(
+        "INSERT OR IGNORE INTO known_devices(user_id, device_id, user_agent)
+        VALUES ($1, $2, $3);",
+        user_id,
+        device.device_id,
+        device.user_agent
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .rows_affected();
+
+    if inserted == 0 {
+        sqlx::query!(
+            "UPDATE known_devices SET last_seen_at = CURRENT_TIMESTAMP
+            WHERE user_id = $1 AND device_id = $2 AND user_agent = $3;",
+            user_id,
+            device.device_id,
+            device.user_agent
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    }
+
+    Ok(inserted > 0)
+}
+
+/// Let the user know someone logged in to their account from a new device
+pub fn notify_new_device(
+    mailer: web::Data<AsyncSmtpTransport<Tokio1Executor>>,
+    hostname: &str,
+    user: &User,
+    device: &Device,
+    method: LoginMethod,
+) -> actix_web::Result<()> {
+    let message = build_email_for_user(
+        &user.email,
+        "New login to Silly Goals",
+        &format!(
+            "Your Silly Goals account was just logged in to from a device we \
+            haven't seen before.\n\n\
+            When: {}\n\
+            Device: {}\n\
+            IP address: {}\n\
+            Logged in with: {}\n\n\
+            If this was you, you don't need to do anything. If not, sign that \
+            device out and check your email account is secure: \
+            https://{}/profile",
+            Utc::now().format("%B %-d, %Y at %H:%M UTC"),
+            device.user_agent,
+            device.ip.as_deref().unwrap_or("Unknown"),
+            method.name(),
+            hostname
+        ),
+    )?;
+
+    tokio::spawn(async move {
+        match mailer.send(message).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!("Failed to send message: {}", e);
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,228 @@
+use actix_session::Session;
+use actix_web::{
+    error::{ErrorBadRequest, ErrorInternalServerError},
+    HttpRequest,
+};
+use base64::{engine::general_purpose, Engine};
+use chrono::{NaiveDateTime, Utc};
+use log::warn;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{pool::PoolConnection, Sqlite};
+
+use crate::{client_ip::client_ip, SessionValue};
+
+const DEFAULT_PER_RECIPIENT: i64 = 10;
+const DEFAULT_PER_IP: i64 = 30;
+
+/// Emails are counted over this many minutes
+const WINDOW_MINUTES: i64 = 60;
+
+/// Sends are kept this long, so operators can look back at blocked attempts
+const KEEP_DAYS: i64 = 30;
+
+/// How many emails the public forms can send in an hour, to one address and
+/// from one ip address, so they can't be used to flood someone's inbox. Set
+/// with `EMAIL_LIMIT_PER_RECIPIENT` and `EMAIL_LIMIT_PER_IP`.
+///
+/// `EMAIL_PROOF_OF_WORK_BITS` turns on a proof of work for the login and
+/// registration forms, making the browser spend a moment hashing before it can
+/// ask for an email. Around 16 takes a second or two.
+#[derive(Clone, Copy, Debug)]
+pub struct EmailLimits {
+    pub per_recipient: i64,
+    pub per_ip: i64,
+    pub proof_of_work_bits: u32,
+}
+
+impl EmailLimits {
+    pub fn from_env() -> Self {
+        let var = |name: &str| dotenvy::var(name).ok().and_then(|v| v.parse().ok());
+        Self {
+            per_recipient: var("EMAIL_LIMIT_PER_RECIPIENT")
+                .filter(|limit| *limit > 0)
+                .unwrap_or(DEFAULT_PER_RECIPIENT),
+            per_ip: var("EMAIL_LIMIT_PER_IP")
+                .filter(|limit| *limit > 0)
+                .unwrap_or(DEFAULT_PER_IP),
+            proof_of_work_bits: dotenvy::var("EMAIL_PROOF_OF_WORK_BITS")
+                .ok()
+                .and_then(|bits| bits.parse().ok())
+                .filter(|bits| *bits <= 32)
+                .unwrap_or(0),
+        }
+    }
+}
+
+impl Default for EmailLimits {
+    fn default() -> Self {
+        Self {
+            per_recipient: DEFAULT_PER_RECIPIENT,
+            per_ip: DEFAULT_PER_IP,
+            proof_of_work_bits: 0,
+        }
+    }
+}
+
+/// Which form sent an email
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq)]
+#[sqlx(rename_all = "snake_case")]
+pub enum EmailKind {
+    Register,
+    Login,
+    LoginCode,
+    ChangeEmail,
+}
+
+/// Count an email about to be sent against the limits. Returns how many
+/// minutes to wait if it would go over, in which case it shouldn't be sent.
+pub async fn check_email_limits(
+    conn: &mut PoolConnection<Sqlite>,
+    req: &HttpRequest,
+    limits: &EmailLimits,
+    kind: EmailKind,
+    recipient: &str,
+) -> actix_web::Result<Option<i64>> {
+    let recipient = recipient.to_lowercase();
+    let ip = client_ip(req);
+
+    sqlx::query!(
+        "DELETE FROM email_sends WHERE created_at < datetime('now', '-' || $1 || ' days');",
+        KEEP_DAYS
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    let to_recipient = sqlx::query!(
+        r#"SELECT COUNT(id) as "count!: i64", MIN(created_at) as "oldest: NaiveDateTime"
+        FROM email_sends
+        WHERE recipient = $1 AND blocked = 0
+        AND created_at > datetime('now', '-' || $2 || ' minutes');"#,
+        recipient,
+        WINDOW_MINUTES
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    let from_ip = sqlx::query!(
+        r#"SELECT COUNT(id) as "count!: i64", MIN(created_at) as "oldest: NaiveDateTime"
+        FROM email_sends
+        WHERE ip = $1 AND blocked = 0
+        AND created_at > datetime('now', '-' || $2 || ' minutes');"#,
+        ip,
+        WINDOW_MINUTES
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    // Whichever limit was hit frees up when its oldest email leaves the window
+    let blocked_until = [
+        (to_recipient.count >= limits.per_recipient)
+            .then_some(to_recipient.oldest)
+            .flatten(),
+        (from_ip.count >= limits.per_ip)
+            .then_some(from_ip.oldest)
+            .flatten(),
+    ]
+    .into_iter()
+    .flatten()
+    .max()
+    .map(|oldest| oldest + chrono::Duration::minutes(WINDOW_MINUTES));
+
+    let blocked = blocked_until.is_some();
+    sqlx::query!(
+        "INSERT INTO email_sends(kind, recipient, ip, blocked) VALUES ($1, $2, $3, $4);",
+        kind,
+        recipient,
+        ip,
+        blocked
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(blocked_until.map(|until| {
+        warn!(
+            "Blocked {:?} email to {} from {}, {} to them and {} from there in the last hour",
+            kind,
+            recipient,
+            ip.as_deref().unwrap_or("an unknown address"),
+            to_recipient.count,
+            from_ip.count
+        );
+        let wait = until - Utc::now().naive_utc();
+        wait.num_minutes().max(0) + 1
+    }))
+}
+
+/// Tell the user how long until they can ask for another email
+pub fn wait_message(wait_minutes: i64) -> String {
+    let plural = if wait_minutes == 1 { "" } else { "s" };
+    format!("Too many emails sent, please try again in {wait_minutes} minute{plural}")
+}
+
+/// A puzzle the browser has to solve before a public form will send an
+/// email: find a nonce where the sha256 of the challenge and nonce starts with
+/// enough zero bits.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PowChallenge(String);
+
+impl SessionValue for PowChallenge {
+    fn save_name() -> &'static str {
+        "pow_challenge"
+    }
+}
+
+impl PowChallenge {
+    /// Start a new challenge for the next form, if proof of work is turned on
+    pub fn issue(session: &Session, limits: &EmailLimits) -> actix_web::Result<Option<String>> {
+        if limits.proof_of_work_bits == 0 {
+            return Ok(None);
+        }
+        let mut challenge_bytes = [0u8; 16];
+        thread_rng().fill(&mut challenge_bytes);
+        let challenge = general_purpose::URL_SAFE_NO_PAD.encode(challenge_bytes);
+        Self(challenge.clone()).save(session)?;
+        Ok(Some(challenge))
+    }
+
+    /// Check the nonce the form sent solves the challenge. Each challenge only
+    /// works once.
+    pub fn verify_from_session(
+        session: &Session,
+        limits: &EmailLimits,
+        nonce: Option<&str>,
+    ) -> actix_web::Result<()> {
+        if limits.proof_of_work_bits == 0 {
+            return Ok(());
+        }
+        let challenge = Self::get(session).map_err(ErrorInternalServerError)?;
+        Self::remove(session);
+        match (challenge, nonce) {
+            (Some(challenge), Some(nonce))
+                if leading_zero_bits(&Sha256::digest(format!("{}{}", challenge.0, nonce)))
+                    >= limits.proof_of_work_bits =>
+            {
+                Ok(())
+            }
+            _ => Err(ErrorBadRequest("Proof of work missing or incorrect")),
+        }
+    }
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        if *byte == 0 {
+            bits += 8;
+        } else {
+            bits += byte.leading_zeros();
+            break;
+        }
+    }
+    bits
+}
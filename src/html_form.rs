@@ -0,0 +1,37 @@
+use actix_web::{
+    dev::Payload,
+    error::{Error as AwError, ErrorBadRequest},
+    web::Bytes,
+    FromRequest, HttpRequest,
+};
+use futures::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+
+/// Works like `web::Form`, but repeated keys (from checkboxes or multiple
+/// selects) can be collected into a `Vec`
+#[derive(Debug)]
+pub struct HtmlForm<T>(pub T);
+
+impl<T: DeserializeOwned + 'static> FromRequest for HtmlForm<T> {
+    type Error = AwError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let body = Bytes::from_request(req, payload);
+
+        Box::pin(async move {
+            let body = body.await?;
+            serde_html_form::from_bytes(&body)
+                .map(Self)
+                .map_err(ErrorBadRequest)
+        })
+    }
+}
+
+impl<T> std::ops::Deref for HtmlForm<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
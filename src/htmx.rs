@@ -108,6 +108,15 @@ pub enum NotificationVariant {
     Failure,
 }
 
+/// A button shown in a notification that sends a PATCH request when clicked,
+/// used to undo whatever the notification is about
+#[derive(Serialize)]
+pub struct NotificationAction {
+    pub label: String,
+    pub url: String,
+    pub values: serde_json::Value,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NotificationEvent {
@@ -115,6 +124,8 @@ pub struct NotificationEvent {
     message: String,
     variant: NotificationVariant,
     auto_hide: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    action: Option<NotificationAction>,
 }
 
 pub fn hx_trigger_notification(
@@ -123,14 +134,31 @@ pub fn hx_trigger_notification(
     variant: NotificationVariant,
     auto_hide: bool,
 ) -> (String, String) {
-    let event = json!({
-        "notify": NotificationEvent {
-            title,
-            message,
-            variant,
-            auto_hide,
-        },
+    notification_header(NotificationEvent {
+        title,
+        message,
+        variant,
+        auto_hide,
+        action: None,
+    })
+}
+
+pub fn hx_trigger_notification_with_action(
+    title: String,
+    message: String,
+    variant: NotificationVariant,
+    action: NotificationAction,
+) -> (String, String) {
+    notification_header(NotificationEvent {
+        title,
+        message,
+        variant,
+        auto_hide: true,
+        action: Some(action),
     })
-    .to_string();
+}
+
+fn notification_header(event: NotificationEvent) -> (String, String) {
+    let event = json!({ "notify": event }).to_string();
     ("HX-Trigger-After-Swap".into(), event)
 }
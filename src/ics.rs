@@ -0,0 +1,106 @@
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+use crate::GoalWithGroup;
+
+/// Lines longer than this many octets must be folded (RFC 5545 3.1)
+const MAX_LINE_OCTETS: usize = 75;
+
+/// Escape a TEXT value (RFC 5545 3.3.11)
+fn escape_text(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+        .replace('\r', "")
+}
+
+/// Write a content line, folding it so no line is longer than 75 octets
+/// without splitting any multibyte characters.
+fn push_line(out: &mut String, line: &str) {
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            out.push_str("\r\n ");
+            // the leading space counts towards the length of the new line
+            octets = 1;
+        }
+        out.push(c);
+        octets += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// Build an iCalendar feed with an all day event on the deadline of each goal.
+/// Goals in the final stage of their tone are marked as done.
+pub fn goals_calendar(hostname: &str, goals: &[GoalWithGroup], now: NaiveDateTime) -> String {
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, "PRODID:-//Silly Goals//Goal Deadlines//EN");
+    push_line(&mut out, "CALSCALE:GREGORIAN");
+    push_line(&mut out, "METHOD:PUBLISH");
+    push_line(&mut out, "X-WR-CALNAME:Silly Goals");
+
+    let timestamp = now.format("%Y%m%dT%H%M%SZ");
+    for goal in goals {
+        let Ok(deadline) = NaiveDate::parse_from_str(&goal.deadline, "%Y-%m-%d") else {
+            continue;
+        };
+        // All day events end the day after, which doesn't exist for the last date
+        let Some(end) = deadline.checked_add_signed(Duration::days(1)) else {
+            continue;
+        };
+        let stage = usize::try_from(goal.stage)
+            .ok()
+            .and_then(|s| goal.tone_stages.get(s))
+            .map(|s| s.as_str())
+            .unwrap_or("unknown");
+        let complete = goal.stage as usize + 1 >= goal.tone_stages.len();
+
+        let mut description = String::new();
+        if let Some(desc) = &goal.description {
+            description.push_str(desc);
+            description.push_str("\n\n");
+        }
+        description.push_str(&format!("Group: {}\nStage: {}", goal.group_title, stage));
+
+        push_line(&mut out, "BEGIN:VEVENT");
+        push_line(&mut out, &format!("UID:goal-{}@{}", goal.id, hostname));
+        push_line(&mut out, &format!("DTSTAMP:{}", timestamp));
+        push_line(
+            &mut out,
+            &format!("DTSTART;VALUE=DATE:{}", deadline.format("%Y%m%d")),
+        );
+        push_line(
+            &mut out,
+            &format!("DTEND;VALUE=DATE:{}", end.format("%Y%m%d")),
+        );
+        let summary = if complete {
+            format!("✓ {}", goal.title)
+        } else {
+            goal.title.clone()
+        };
+        push_line(&mut out, &format!("SUMMARY:{}", escape_text(&summary)));
+        push_line(
+            &mut out,
+            &format!("DESCRIPTION:{}", escape_text(&description)),
+        );
+        push_line(
+            &mut out,
+            &format!("CATEGORIES:{}", escape_text(&goal.group_title)),
+        );
+        push_line(
+            &mut out,
+            &format!(
+                "URL:https://{}/groups/{}/goals/{}",
+                hostname, goal.group_id, goal.id
+            ),
+        );
+        push_line(&mut out, "TRANSP:TRANSPARENT");
+        push_line(&mut out, "END:VEVENT");
+    }
+
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
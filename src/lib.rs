@@ -1,12 +1,29 @@
+pub mod account_deletion;
+pub mod calendar;
+pub mod client_ip;
 pub mod csrf_token;
+pub mod devices;
+pub mod email_limits;
+pub mod html_form;
 pub mod htmx;
+pub mod ics;
+pub mod login_links;
+pub mod login_sessions;
 pub mod mail;
+pub mod markdown;
+pub mod members;
 pub mod queries;
+pub mod reauth;
+pub mod recovery_codes;
 pub mod routes;
+pub mod security_events;
 pub mod session_values;
+pub mod share;
+pub mod stats;
 pub mod templates;
+pub mod trash;
 
-use std::str::FromStr;
+use std::{cmp::Reverse, str::FromStr};
 
 use actix_session::Session;
 use actix_web::{
@@ -16,6 +33,7 @@ use actix_web::{
     middleware::ErrorHandlerResponse,
 };
 use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
 use log::error;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
@@ -25,6 +43,8 @@ use sqlx::{
 };
 use webauthn_rs::prelude::PasskeyRegistration;
 
+use crate::members::GroupRole;
+
 pub trait SessionValue: Clone + Serialize + for<'a> Deserialize<'a> {
     fn save(&self, session: &Session) -> actix_web::Result<()> {
         session
@@ -103,6 +123,10 @@ pub struct Group {
     pub description: Option<String>,
     pub user_id: i64,
     pub tone_id: i64,
+    pub icon: Option<String>,
+    pub color: Option<LabelColor>,
+    pub pinned: bool,
+    pub role: GroupRole,
 }
 
 #[derive(Clone, Debug)]
@@ -117,6 +141,13 @@ pub struct GroupWithInfo {
     pub deadline: DeadlineType,
     pub tone_id: i64,
     pub user_id: i64,
+    pub goal_sort: GoalSort,
+    pub icon: Option<String>,
+    pub color: Option<LabelColor>,
+    pub pinned: bool,
+    pub role: GroupRole,
+    /// Whether anyone besides the owner is a member
+    pub shared: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -131,6 +162,11 @@ pub struct GroupDisplay {
     pub deadline: DeadlineType,
     pub tone_id: i64,
     pub user_id: i64,
+    pub goal_sort: GoalSort,
+    pub icon: Option<String>,
+    pub color: Option<LabelColor>,
+    pub role: GroupRole,
+    pub shared: bool,
 }
 
 impl From<GroupWithInfo> for GroupDisplay {
@@ -150,6 +186,11 @@ impl From<GroupWithInfo> for GroupDisplay {
             deadline: value.deadline,
             tone_id: value.tone_id,
             user_id: value.user_id,
+            goal_sort: value.goal_sort,
+            icon: value.icon,
+            color: value.color,
+            role: value.role,
+            shared: value.shared,
         }
     }
 }
@@ -162,14 +203,298 @@ impl From<GroupWithInfo> for Group {
             description: value.description,
             tone_id: value.tone_id,
             user_id: value.user_id,
+            icon: value.icon,
+            color: value.color,
+            pinned: value.pinned,
+            role: value.role,
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 pub struct GroupLink {
     id: i64,
     title: String,
+    icon: Option<String>,
+    color: Option<LabelColor>,
+    pinned: bool,
+    role: GroupRole,
+}
+
+impl GroupLink {
+    /// Whether goals from another group can be moved into this one
+    pub fn takes_goals_from(&self, group_id: i64) -> bool {
+        self.id != group_id && self.role.can_edit_goals()
+    }
+}
+
+/// A group that has been put away. It can still be looked at, but not changed
+/// until it is restored.
+#[derive(Clone, Debug)]
+pub struct ArchivedGroup {
+    pub id: i64,
+    pub title: String,
+    pub icon: Option<String>,
+    pub color: Option<LabelColor>,
+    pub goal_count: i64,
+    pub archived_at: NaiveDateTime,
+    pub role: GroupRole,
+}
+
+/// Shapes that can be picked as a group's icon instead of an emoji
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupIcon {
+    Star,
+    Heart,
+    Bolt,
+    Home,
+    Flag,
+    Moon,
+    Circle,
+    Square,
+    Triangle,
+    Diamond,
+}
+
+impl GroupIcon {
+    pub fn all() -> [GroupIcon; 10] {
+        [
+            GroupIcon::Star,
+            GroupIcon::Heart,
+            GroupIcon::Bolt,
+            GroupIcon::Home,
+            GroupIcon::Flag,
+            GroupIcon::Moon,
+            GroupIcon::Circle,
+            GroupIcon::Square,
+            GroupIcon::Triangle,
+            GroupIcon::Diamond,
+        ]
+    }
+
+    pub fn from_name(name: &str) -> Option<GroupIcon> {
+        GroupIcon::all()
+            .into_iter()
+            .find(|icon| icon.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GroupIcon::Star => "star",
+            GroupIcon::Heart => "heart",
+            GroupIcon::Bolt => "bolt",
+            GroupIcon::Home => "home",
+            GroupIcon::Flag => "flag",
+            GroupIcon::Moon => "moon",
+            GroupIcon::Circle => "circle",
+            GroupIcon::Square => "square",
+            GroupIcon::Triangle => "triangle",
+            GroupIcon::Diamond => "diamond",
+        }
+    }
+
+    /// The shape to put inside a 24x24 svg
+    pub fn shape(&self) -> &'static str {
+        match self {
+            GroupIcon::Star => {
+                r#"<polygon points="12,2 14.9,8.6 22,9.3 16.6,14 18.2,21 12,17.3 5.8,21 7.4,14 2,9.3 9.1,8.6" />"#
+            }
+            GroupIcon::Heart => {
+                r#"<path d="M12 21s-7.5-4.6-9.5-9.3C1.1 8.4 3.2 5 6.6 5c2 0 3.5 1.1 5.4 3 1.9-1.9 3.4-3 5.4-3 3.4 0 5.5 3.4 4.1 6.7C19.5 16.4 12 21 12 21z" />"#
+            }
+            GroupIcon::Bolt => r#"<polygon points="13,2 4,14 11,14 10,22 20,9 13,9" />"#,
+            GroupIcon::Home => r#"<path d="M3 11l9-8 9 8v10h-6v-6H9v6H3z" />"#,
+            GroupIcon::Flag => r#"<path d="M5 2h2v20H5zM8 3h11l-2.5 4.5L19 12H8z" />"#,
+            GroupIcon::Moon => r#"<path d="M20 14.5A8 8 0 0 1 9.5 4a8 8 0 1 0 10.5 10.5z" />"#,
+            GroupIcon::Circle => r#"<circle cx="12" cy="12" r="9" />"#,
+            GroupIcon::Square => r#"<rect x="4" y="4" width="16" height="16" rx="2" />"#,
+            GroupIcon::Triangle => r#"<polygon points="12,3 22,20 2,20" />"#,
+            GroupIcon::Diamond => r#"<polygon points="12,2 22,12 12,22 2,12" />"#,
+        }
+    }
+}
+
+impl std::fmt::Display for GroupIcon {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Check the icon picked for a group, which has to be one of the built-in
+/// icons or a single emoji. Blank means no icon.
+pub fn clean_group_icon(icon: Option<&str>) -> Result<Option<String>> {
+    let icon = match icon.map(str::trim) {
+        None | Some("") => return Ok(None),
+        Some(icon) => icon,
+    };
+    if GroupIcon::from_name(icon).is_some() {
+        return Ok(Some(icon.to_string()));
+    }
+    // Emoji can be built from several code points, so allow a few
+    if icon.chars().count() > 8 || icon.chars().any(|c| c.is_ascii() || c.is_whitespace()) {
+        return Err(anyhow!("Pick a built-in icon or a single emoji"));
+    }
+    Ok(Some(icon.to_string()))
+}
+
+#[derive(sqlx::Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum LabelColor {
+    Gray,
+    Red,
+    Orange,
+    Amber,
+    Green,
+    Teal,
+    Sky,
+    Blue,
+    Violet,
+    Pink,
+}
+
+impl LabelColor {
+    pub fn all() -> [LabelColor; 10] {
+        [
+            LabelColor::Gray,
+            LabelColor::Red,
+            LabelColor::Orange,
+            LabelColor::Amber,
+            LabelColor::Green,
+            LabelColor::Teal,
+            LabelColor::Sky,
+            LabelColor::Blue,
+            LabelColor::Violet,
+            LabelColor::Pink,
+        ]
+    }
+}
+
+impl std::fmt::Display for LabelColor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            LabelColor::Gray => "gray",
+            LabelColor::Red => "red",
+            LabelColor::Orange => "orange",
+            LabelColor::Amber => "amber",
+            LabelColor::Green => "green",
+            LabelColor::Teal => "teal",
+            LabelColor::Sky => "sky",
+            LabelColor::Blue => "blue",
+            LabelColor::Violet => "violet",
+            LabelColor::Pink => "pink",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Label {
+    pub id: i64,
+    pub name: String,
+    pub color: LabelColor,
+}
+
+/// How the goals in each stage of a group are ordered. Each user picks their
+/// own for each group.
+#[derive(sqlx::Type, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum GoalSort {
+    /// The order the goals were dragged into
+    #[default]
+    Manual,
+    Priority,
+    Deadline,
+    Updated,
+}
+
+impl GoalSort {
+    pub fn all() -> [GoalSort; 4] {
+        [
+            GoalSort::Manual,
+            GoalSort::Priority,
+            GoalSort::Deadline,
+            GoalSort::Updated,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GoalSort::Manual => "Manual",
+            GoalSort::Priority => "Priority",
+            GoalSort::Deadline => "Deadline",
+            GoalSort::Updated => "Recently updated",
+        }
+    }
+
+    /// Sort goals in place. Ties are left in creation order.
+    pub fn sort(&self, goals: &mut [Goal]) {
+        goals.sort_by_key(|g| g.id);
+        match self {
+            GoalSort::Manual => goals.sort_by_key(|g| g.position),
+            GoalSort::Priority => goals.sort_by_key(|g| Reverse(g.priority)),
+            // goals without deadlines go last
+            GoalSort::Deadline => goals.sort_by_key(|g| (g.deadline.is_none(), g.deadline.clone())),
+            GoalSort::Updated => goals.sort_by_key(|g| Reverse(g.updated_at)),
+        }
+    }
+}
+
+impl std::fmt::Display for GoalSort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            GoalSort::Manual => "manual",
+            GoalSort::Priority => "priority",
+            GoalSort::Deadline => "deadline",
+            GoalSort::Updated => "updated",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(sqlx::Type, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[repr(i32)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low = 0,
+    #[default]
+    Normal = 1,
+    High = 2,
+    Urgent = 3,
+}
+
+impl Priority {
+    pub fn all() -> [Priority; 4] {
+        [
+            Priority::Low,
+            Priority::Normal,
+            Priority::High,
+            Priority::Urgent,
+        ]
+    }
+
+    /// The form value for this priority
+    pub fn value(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+            Priority::Urgent => "urgent",
+        }
+    }
+}
+
+impl std::fmt::Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Priority::Low => "Low",
+            Priority::Normal => "Normal",
+            Priority::High => "High",
+            Priority::Urgent => "Urgent",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -180,8 +505,82 @@ pub struct Goal {
     pub stage: i64,
     pub group_id: i64,
     pub deadline: Option<String>,
+    pub priority: Priority,
+    pub updated_at: NaiveDateTime,
+    pub position: i64,
+    pub labels: Json<Vec<Label>>,
+    /// Names of whoever added the goal and last moved it to another stage or
+    /// group, shown on shared groups
+    pub created_by: Option<String>,
+    pub moved_by: Option<String>,
+    pub moved_at: Option<NaiveDateTime>,
 }
 
+impl Goal {
+    pub fn has_label(&self, label_id: &i64) -> bool {
+        self.labels.iter().any(|l| l.id == *label_id)
+    }
+}
+
+/// Find the stage a goal should be in after moving to a group whose tone has a
+/// different number of stages. Finished goals stay finished and the rest keep
+/// their stage unless the new tone runs out of stages before the last one.
+pub fn remap_stage(stage: i64, from_stages: usize, to_stages: usize) -> i64 {
+    let to_last = to_stages.saturating_sub(1) as i64;
+    if from_stages == to_stages {
+        stage.clamp(0, to_last)
+    } else if stage + 1 >= from_stages as i64 {
+        to_last
+    } else {
+        stage.clamp(0, (to_last - 1).max(0))
+    }
+}
+
+/// A goal with a deadline along with the group info needed to show it outside
+/// of its group
+#[derive(Clone, Debug)]
+pub struct GoalWithGroup {
+    pub id: i64,
+    pub title: String,
+    pub description: Option<String>,
+    pub stage: i64,
+    pub deadline: String,
+    pub group_id: i64,
+    pub group_title: String,
+    pub group_icon: Option<String>,
+    pub group_color: Option<LabelColor>,
+    pub tone_stages: Json<Vec<String>>,
+    pub labels: Json<Vec<Label>>,
+}
+
+impl GoalWithGroup {
+    pub fn has_label(&self, label_id: &i64) -> bool {
+        self.labels.iter().any(|l| l.id == *label_id)
+    }
+}
+
+/// A goal matching a search. Matched terms in the title and snippet are
+/// wrapped in the `SEARCH_MATCH_START` and `SEARCH_MATCH_END` markers.
+#[derive(Clone, Debug)]
+pub struct GoalSearchResult {
+    pub id: i64,
+    pub group_id: i64,
+    pub group_title: String,
+    pub title: String,
+    pub snippet: Option<String>,
+}
+
+/// A group matching a search, marked up the same as `GoalSearchResult`
+#[derive(Clone, Debug)]
+pub struct GroupSearchResult {
+    pub id: i64,
+    pub title: String,
+    pub snippet: Option<String>,
+}
+
+pub const SEARCH_MATCH_START: char = '\u{2}';
+pub const SEARCH_MATCH_END: char = '\u{3}';
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct WebauthnCredential {
     pub id: Uuid,
@@ -189,6 +588,25 @@ pub struct WebauthnCredential {
     pub passkey: String,
 }
 
+/// A passkey as listed on the profile page
+#[derive(Debug, Clone)]
+pub struct SavedPasskey {
+    pub id: Uuid,
+    pub credential_id: Option<String>,
+}
+
+impl SavedPasskey {
+    /// The end of the authenticator's id, to tell passkeys apart
+    pub fn short_id(&self) -> String {
+        let id = self
+            .credential_id
+            .clone()
+            .unwrap_or_else(|| self.id.to_string());
+        let start = id.len().saturating_sub(6);
+        id[start..].to_owned()
+    }
+}
+
 pub async fn seed_db(pool: &SqlitePool) {
     let mut conn = pool.acquire().await.expect("to connect to database");
     let email = "rickhenry@rickhenry.dev";
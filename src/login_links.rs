@@ -0,0 +1,17 @@
+use base64::{engine::general_purpose, Engine};
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+
+/// How long an emailed login link works for
+pub const LOGIN_LINK_MINUTES: i64 = 15;
+
+pub fn new_login_link_token() -> String {
+    let mut token_bytes = [0u8; 32];
+    thread_rng().fill(&mut token_bytes);
+    general_purpose::URL_SAFE_NO_PAD.encode(token_bytes)
+}
+
+/// Hash a secret before storing it, so the database alone isn't enough to use it
+pub fn hash_token(token: &str) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,211 @@
+use std::{fmt, str::FromStr};
+
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{
+    error::{ErrorForbidden, ErrorInternalServerError, ErrorUnauthorized},
+    HttpMessage, HttpRequest,
+};
+use anyhow::anyhow;
+use base64::{engine::general_purpose, Engine};
+use chrono::NaiveDateTime;
+use log::error;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use sqlx::{pool::PoolConnection, types::Uuid, Sqlite};
+
+use crate::{
+    security_events::{record_security_event, SecurityEventKind},
+    session_values::UsedWebauthn,
+    SessionValue,
+};
+
+/// How long a login lasts without any visits. The identity middleware uses
+/// the same deadline, so older sessions can be cleared away.
+pub const IDLE_HOURS: u64 = 24;
+
+/// How a user proved who they were when they logged in
+#[derive(sqlx::Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum LoginMethod {
+    Code,
+    Link,
+    Passkey,
+    Recovery,
+}
+
+impl LoginMethod {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LoginMethod::Code => "Email code",
+            LoginMethod::Link => "Email link",
+            LoginMethod::Passkey => "Passkey",
+            LoginMethod::Recovery => "Recovery code",
+        }
+    }
+}
+
+/// A device the user is logged in on
+#[derive(Clone, Debug)]
+pub struct LoginSession {
+    pub id: i64,
+    pub session_id: String,
+    pub method: LoginMethod,
+    pub user_agent: String,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+}
+
+/// What is stored in the identity cookie: the user and which of their login
+/// sessions this is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoginIdentity {
+    pub userid: Uuid,
+    pub session_id: String,
+}
+
+impl LoginIdentity {
+    pub fn from_identity(identity: &Identity) -> actix_web::Result<Self> {
+        let id = identity.id().map_err(ErrorInternalServerError)?;
+        id.parse().map_err(ErrorUnauthorized)
+    }
+}
+
+impl fmt::Display for LoginIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.userid, self.session_id)
+    }
+}
+
+impl FromStr for LoginIdentity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Identities from before sessions were tracked only have the user id,
+        // so they fail here and have to log in again
+        let (userid, session_id) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Identity has no login session"))?;
+        Ok(Self {
+            userid: Uuid::parse_str(userid)?,
+            session_id: session_id.to_owned(),
+        })
+    }
+}
+
+fn new_session_id() -> String {
+    let mut id_bytes = [0u8; 32];
+    thread_rng().fill(&mut id_bytes);
+    general_purpose::URL_SAFE_NO_PAD.encode(id_bytes)
+}
+
+/// Turn a user agent header into something short like "Firefox on Windows"
+pub fn summarize_user_agent(user_agent: &str) -> String {
+    let browser = if user_agent.contains("Edg/") {
+        "Edge"
+    } else if user_agent.contains("OPR/") || user_agent.contains("Opera") {
+        "Opera"
+    } else if user_agent.contains("Firefox/") || user_agent.contains("FxiOS/") {
+        "Firefox"
+    } else if user_agent.contains("Chrome/") || user_agent.contains("CriOS/") {
+        "Chrome"
+    } else if user_agent.contains("Safari/") {
+        "Safari"
+    } else {
+        "Unknown browser"
+    };
+
+    let os = if user_agent.contains("iPhone") || user_agent.contains("iPad") {
+        "iOS"
+    } else if user_agent.contains("Android") {
+        "Android"
+    } else if user_agent.contains("Windows") {
+        "Windows"
+    } else if user_agent.contains("Mac OS X") || user_agent.contains("Macintosh") {
+        "macOS"
+    } else if user_agent.contains("CrOS") {
+        "ChromeOS"
+    } else if user_agent.contains("Linux") {
+        "Linux"
+    } else {
+        "an unknown system"
+    };
+
+    format!("{browser} on {os}")
+}
+
+/// Record a new login session and log the user in with it
+pub async fn login(
+    conn: &mut PoolConnection<Sqlite>,
+    req: &HttpRequest,
+    session: &Session,
+    userid: Uuid,
+    method: LoginMethod,
+) -> actix_web::Result<()> {
+    UsedWebauthn::from(method == LoginMethod::Passkey).save(session)?;
+    let user_agent = req
+        .headers()
+        .get("User-Agent")
+        .and_then(|agent| agent.to_str().ok())
+        .map(summarize_user_agent)
+        .unwrap_or_else(|| "Unknown device".into());
+    let session_id = new_session_id();
+
+    sqlx::query!(
+        "DELETE FROM login_sessions
+        WHERE user_id = (SELECT id FROM users WHERE userid = $1)
+        AND last_seen_at < datetime('now', '-' || $2 || ' hours');",
+        userid,
+        IDLE_HOURS as i64
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    // Accounts waiting to be deleted are locked, whichever way they log in
+    let inserted = sqlx::query!(
+        "INSERT INTO login_sessions(user_id, session_id, method, user_agent, authenticated_at)
+        SELECT id, $2, $3, $4, CURRENT_TIMESTAMP FROM users
+        WHERE userid = $1 AND deletion_scheduled_for IS NULL;",
+        userid,
+        session_id,
+        method,
+        user_agent
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(|err| {
+        error!("Error communicating with database: {}", err);
+        ErrorInternalServerError(err)
+    })?
+    .rows_affected();
+
+    if inserted == 0 {
+        return Err(ErrorForbidden("This account is being deleted"));
+    }
+
+    let user_id = sqlx::query_scalar!(
+        "SELECT user_id FROM login_sessions WHERE session_id = $1;",
+        session_id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+    record_security_event(
+        conn,
+        req,
+        user_id,
+        SecurityEventKind::Login,
+        Some(method.name()),
+    )
+    .await?;
+
+    let login_identity = LoginIdentity { userid, session_id };
+    Identity::login(&req.extensions(), login_identity.to_string()).map_err(|err| {
+        error!("Error Logging in user: {}", err);
+        ErrorInternalServerError(err)
+    })?;
+
+    Ok(())
+}
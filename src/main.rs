@@ -15,9 +15,16 @@ use env_logger::Env;
 use lettre::{transport::smtp::authentication::Credentials, AsyncSmtpTransport, Tokio1Executor};
 use log::info;
 use silly_goals::{
-    handle_unauthorized,
-    routes::{auth, dashboard, webauthn_routes},
+    account_deletion::{self, DeletionGracePeriod},
+    client_ip::TrustedProxies,
+    email_limits::EmailLimits,
+    handle_unauthorized, login_sessions,
+    routes::{
+        archive, auth, bulk, calendar_feed, dashboard, labels, members, passkeys, reauth, recovery,
+        security, sessions, share, trash, webauthn_routes,
+    },
     seed_db,
+    trash::{purge_periodically, TrashRetention},
 };
 use sqlx::sqlite::SqlitePool;
 use webauthn_rs::prelude::*;
@@ -120,6 +127,32 @@ async fn main() -> Result<(), std::io::Error> {
     info!("Seeding Database");
     seed_db(&pool).await;
 
+    let trash_retention = TrashRetention::from_env();
+    info!(
+        "Emptying the trash of anything older than {} days",
+        trash_retention.0
+    );
+    actix_web::rt::spawn(purge_periodically(pool.clone(), trash_retention));
+
+    let deletion_grace = DeletionGracePeriod::from_env();
+    info!(
+        "Deleting accounts for good {} days after they ask",
+        deletion_grace.0
+    );
+    actix_web::rt::spawn(account_deletion::purge_periodically(pool.clone()));
+
+    let email_limits = EmailLimits::from_env();
+    info!(
+        "Sending at most {} emails an hour to an address, and {} from an ip address",
+        email_limits.per_recipient, email_limits.per_ip
+    );
+
+    let trusted_proxies = TrustedProxies::from_env();
+    info!(
+        "Trusting forwarded ip addresses from {} proxies",
+        trusted_proxies.0.len()
+    );
+
     let redis_uri = dotenvy::var("REDIS_URL").expect("REDIS_URL must be set");
 
     let hostname = dotenvy::var("HOSTNAME").expect("HOSTNAME must be set");
@@ -184,7 +217,7 @@ async fn main() -> Result<(), std::io::Error> {
             ))
             .wrap(
                 IdentityMiddleware::builder()
-                    .visit_deadline(Some(Duration::from_secs(3600 * 24)))
+                    .visit_deadline(Some(Duration::from_secs(3600 * login_sessions::IDLE_HOURS)))
                     .build(),
             )
             .wrap(Compress::default())
@@ -195,6 +228,10 @@ async fn main() -> Result<(), std::io::Error> {
             .service(ResourceFiles::new("/static", generated))
             .app_data(web::Data::new(mailer.clone()))
             .app_data(web::Data::new(hostname.clone()))
+            .app_data(web::Data::new(trash_retention))
+            .app_data(web::Data::new(deletion_grace))
+            .app_data(web::Data::new(email_limits))
+            .app_data(web::Data::new(trusted_proxies.clone()))
             .service(auth::register)
             .service(auth::post_register)
             .service(auth::finish_registration)
@@ -202,15 +239,24 @@ async fn main() -> Result<(), std::io::Error> {
             .service(auth::login_with_code)
             .service(auth::post_login)
             .service(auth::finish_login)
+            .service(auth::login_link)
+            .service(auth::finish_login_link)
             .service(auth::profile)
             .service(auth::profile_edit_name)
             .service(auth::post_profile_edit_name)
             .service(auth::profile_edit_email)
             .service(auth::post_profile_edit_email)
             .service(auth::post_profile_confirm_email)
+            .service(auth::email_change_revert)
+            .service(auth::finish_email_change_revert)
             .service(auth::delete_profile)
+            .service(auth::restore_account)
+            .service(auth::finish_restore_account)
             .service(auth::logout)
             .service(dashboard::dashboard)
+            .service(dashboard::today)
+            .service(dashboard::calendar)
+            .service(dashboard::search)
             .service(dashboard::finish_tutorial)
             .service(dashboard::new_group)
             .service(dashboard::post_new_group)
@@ -219,20 +265,79 @@ async fn main() -> Result<(), std::io::Error> {
             .service(dashboard::delete_group)
             .service(dashboard::post_edit_group)
             .service(dashboard::get_group)
+            .service(dashboard::patch_goal_sort)
+            .service(dashboard::sidebar_groups)
+            .service(dashboard::patch_group_order)
+            .service(dashboard::patch_group_pin)
+            .service(dashboard::group_stats)
+            .service(dashboard::archived_goals)
             .service(dashboard::new_goal)
             .service(dashboard::post_new_goal)
             .service(dashboard::get_goal)
             .service(dashboard::edit_goal)
             .service(dashboard::post_edit_goal)
+            .service(dashboard::move_goal)
+            .service(dashboard::post_move_goal)
             .service(dashboard::patch_goal_tone)
+            .service(dashboard::patch_goal_order)
+            .service(dashboard::patch_goal_deadline)
             .service(dashboard::delete_goal)
             .service(dashboard::dashboard_help_walkthrough)
             .service(dashboard::dashboard_help_general)
             .service(dashboard::dashboard_help_tones)
+            .service(dashboard::markdown_preview)
+            .service(bulk::bulk_actions)
+            .service(bulk::post_bulk_action)
+            .service(labels::labels_page)
+            .service(labels::post_new_label)
+            .service(labels::post_edit_label)
+            .service(labels::delete_label)
+            .service(trash::trash)
+            .service(trash::restore_goals)
+            .service(trash::restore_group)
+            .service(archive::archive)
+            .service(archive::archived_group)
+            .service(archive::archive_group)
+            .service(archive::restore_group)
+            .service(members::group_members)
+            .service(members::post_invite_member)
+            .service(members::patch_member_role)
+            .service(members::delete_member)
+            .service(members::delete_invitation)
+            .service(members::show_invitation)
+            .service(members::show_group_invitation)
+            .service(members::accept_invitation)
+            .service(members::decline_invitation)
+            .service(share::shared_group)
+            .service(share::group_share_links)
+            .service(share::post_share_link)
+            .service(share::revoke_share_link)
+            .service(sessions::profile_sessions)
+            .service(sessions::revoke_session)
+            .service(sessions::revoke_other_sessions)
+            .service(passkeys::profile_passkeys)
+            .service(passkeys::remove_passkey)
+            .service(reauth::reauth)
+            .service(reauth::send_reauth_code)
+            .service(reauth::finish_reauth)
+            .service(recovery::recover)
+            .service(recovery::post_recover)
+            .service(recovery::account_recovered)
+            .service(recovery::profile_recovery_codes)
+            .service(recovery::regenerate_recovery_codes)
+            .service(security::security_history)
+            .service(calendar_feed::calendar_feed)
+            .service(calendar_feed::profile_calendar_feed)
+            .service(calendar_feed::rotate_calendar_feed_token)
+            .service(calendar_feed::disable_calendar_feed)
             .service(webauthn_routes::start_registration)
             .service(webauthn_routes::finish_registration)
             .service(webauthn_routes::start_login)
             .service(webauthn_routes::finish_login)
+            .service(webauthn_routes::start_discoverable_login)
+            .service(webauthn_routes::finish_discoverable_login)
+            .service(webauthn_routes::start_reauth)
+            .service(webauthn_routes::finish_reauth)
             .service(about)
             .service(about_video)
             .service(sitemap)
@@ -0,0 +1,42 @@
+use std::collections::HashSet;
+
+use ammonia::Builder;
+use pulldown_cmark::{html, Event, Options, Parser};
+
+fn options() -> Options {
+    Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH
+}
+
+/// Render a description written in CommonMark to HTML that is safe to put on
+/// the page. Raw HTML in the input is shown as text, and the output is
+/// sanitized as well so only the checkboxes from task lists get through as
+/// inputs.
+pub fn render(input: &str) -> String {
+    let events = Parser::new_ext(input, options()).map(|event| match event {
+        Event::Html(html) => Event::Text(html),
+        event => event,
+    });
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, events);
+
+    Builder::default()
+        .add_tags(&["input"])
+        .add_tag_attributes("input", &["type", "checked", "disabled"])
+        .url_schemes(HashSet::from(["http", "https", "mailto"]))
+        .clean(&unsafe_html)
+        .to_string()
+}
+
+/// Just the words from a description, for places that only have room for a
+/// line of text
+pub fn plain_text(input: &str) -> String {
+    let mut text = String::new();
+    for event in Parser::new_ext(input, options()) {
+        match event {
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak | Event::End(_) => text.push(' '),
+            _ => {}
+        }
+    }
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
@@ -0,0 +1,121 @@
+use actix_web::error::ErrorForbidden;
+use base64::{engine::general_purpose, Engine};
+use chrono::NaiveDateTime;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+
+/// How many days an invitation to a group can be accepted for
+pub const INVITATION_DAYS: i64 = 14;
+
+/// What a member of a group is allowed to do with it. Owners can change the
+/// group and who is in it, editors can change goals and viewers can only look.
+#[derive(sqlx::Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum GroupRole {
+    Owner,
+    Editor,
+    Viewer,
+}
+
+/// Something a member might try to do to a group
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Permission {
+    View,
+    EditGoals,
+    ManageGroup,
+}
+
+impl GroupRole {
+    /// Roles that can be given out in an invitation
+    pub fn invitable() -> [GroupRole; 2] {
+        [GroupRole::Editor, GroupRole::Viewer]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GroupRole::Owner => "Owner",
+            GroupRole::Editor => "Editor",
+            GroupRole::Viewer => "Viewer",
+        }
+    }
+
+    /// What the role lets someone do, to finish "you'll be able to ..."
+    pub fn description(&self) -> &'static str {
+        match self {
+            GroupRole::Owner => "change the group and choose who is in it",
+            GroupRole::Editor => "add, change and move goals",
+            GroupRole::Viewer => "see the goals, but not change them",
+        }
+    }
+
+    pub fn allows(&self, permission: Permission) -> bool {
+        match permission {
+            Permission::View => true,
+            Permission::EditGoals => matches!(self, GroupRole::Owner | GroupRole::Editor),
+            Permission::ManageGroup => *self == GroupRole::Owner,
+        }
+    }
+
+    pub fn can_edit_goals(&self) -> bool {
+        self.allows(Permission::EditGoals)
+    }
+
+    pub fn can_manage(&self) -> bool {
+        self.allows(Permission::ManageGroup)
+    }
+
+    /// Forbid the request unless this role allows `permission`
+    pub fn require(&self, permission: Permission) -> actix_web::Result<()> {
+        if self.allows(permission) {
+            Ok(())
+        } else {
+            Err(ErrorForbidden("You don't have permission to do that"))
+        }
+    }
+}
+
+impl std::fmt::Display for GroupRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            GroupRole::Owner => "owner",
+            GroupRole::Editor => "editor",
+            GroupRole::Viewer => "viewer",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GroupMember {
+    pub user_id: i64,
+    pub name: Option<String>,
+    pub email: String,
+    pub role: GroupRole,
+}
+
+/// An invitation that hasn't been answered yet, as the group's owner sees it
+#[derive(Clone, Debug)]
+pub struct GroupInvitation {
+    pub id: i64,
+    pub email: String,
+    pub role: GroupRole,
+    pub created_at: NaiveDateTime,
+}
+
+/// An invitation as the person it was sent to sees it
+#[derive(Clone, Debug)]
+pub struct PendingInvitation {
+    pub id: i64,
+    pub group_id: i64,
+    pub group_title: String,
+    pub email: String,
+    pub role: GroupRole,
+    pub invited_by: String,
+}
+
+pub fn new_invitation_token() -> String {
+    let mut token_bytes = [0u8; 32];
+    thread_rng().fill(&mut token_bytes);
+    general_purpose::URL_SAFE_NO_PAD.encode(token_bytes)
+}
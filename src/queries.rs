@@ -1,28 +1,40 @@
 use actix_identity::Identity;
 use actix_web::error::{ErrorInternalServerError, ErrorNotFound, ErrorUnauthorized};
 use anyhow::anyhow;
+use chrono::{NaiveDate, NaiveDateTime};
 use log::error;
 use sqlx::{
     pool::PoolConnection,
     types::{Json, Uuid},
-    Sqlite,
+    Sqlite, SqlitePool,
 };
 
-use crate::{DeadlineType, Goal, GoalBehavior, GroupLink, GroupWithInfo, User};
+use crate::{
+    login_sessions::{LoginIdentity, LoginMethod, LoginSession},
+    members::{GroupInvitation, GroupMember, GroupRole, PendingInvitation, INVITATION_DAYS},
+    share::{ShareLink, SharedGroup},
+    stats::StageTransition,
+    trash::{DeletedGoal, DeletedGroup, TrashRetention},
+    ArchivedGroup, DeadlineType, Goal, GoalBehavior, GoalSearchResult, GoalSort, GoalWithGroup,
+    Group, GroupLink, GroupSearchResult, GroupWithInfo, Label, LabelColor, Priority, User,
+};
 
 pub async fn get_user_from_identity(
     conn: &mut PoolConnection<Sqlite>,
     identity: &Identity,
 ) -> actix_web::Result<User> {
-    let userid = identity.id().map_err(ErrorInternalServerError)?;
-    let user_uuid = Uuid::parse_str(&userid).map_err(ErrorInternalServerError)?;
-    sqlx::query_as!(
+    let login = LoginIdentity::from_identity(identity)?;
+    // The login session has to still exist, so revoked sessions are logged
+    // out on their next request
+    let user = sqlx::query_as!(
         User,
-        r#"SELECT id, name, userid as "userid: Uuid", email, is_new_user FROM users
-            WHERE userid = $1"#,
-        user_uuid
+        r#"SELECT users.id, name, userid as "userid: Uuid", email, is_new_user FROM users
+            JOIN login_sessions ON login_sessions.user_id = users.id
+            WHERE userid = $1 AND login_sessions.session_id = $2"#,
+        login.userid,
+        login.session_id
     )
-    .fetch_one(conn)
+    .fetch_one(&mut *conn)
     .await
     .map_err(|err| match err {
         sqlx::error::Error::RowNotFound => ErrorUnauthorized(err),
@@ -30,7 +42,18 @@ pub async fn get_user_from_identity(
             error!("Error communicating with database: {}", err);
             ErrorInternalServerError(err)
         }
-    })
+    })?;
+
+    sqlx::query!(
+        "UPDATE login_sessions SET last_seen_at = CURRENT_TIMESTAMP
+        WHERE session_id = $1 AND last_seen_at < datetime('now', '-5 minutes');",
+        login.session_id
+    )
+    .execute(conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(user)
 }
 
 pub async fn get_user_by_email(
@@ -53,6 +76,40 @@ pub async fn get_user_by_email(
 
 /// Check whether a user exists. Returns Ok(()) if the use exists, anyhow error
 /// if not
+pub async fn get_user_by_id(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+) -> actix_web::Result<User> {
+    sqlx::query_as!(
+        User,
+        r#"SELECT id, email, name, userid as "userid: Uuid", is_new_user FROM users WHERE id = $1"#,
+        user_id,
+    )
+    .fetch_one(conn)
+    .await
+    .map_err(|err| {
+        error!("Error communicating with database: {}", err);
+        ErrorInternalServerError(err)
+    })
+}
+
+pub async fn get_user_by_userid(
+    conn: &mut PoolConnection<Sqlite>,
+    userid: Uuid,
+) -> actix_web::Result<User> {
+    sqlx::query_as!(
+        User,
+        r#"SELECT id, email, name, userid as "userid: Uuid", is_new_user FROM users WHERE userid = $1"#,
+        userid,
+    )
+    .fetch_one(conn)
+    .await
+    .map_err(|err| {
+        error!("Error communicating with database: {}", err);
+        ErrorInternalServerError(err)
+    })
+}
+
 pub async fn check_for_user_from_identity(
     conn: &mut PoolConnection<Sqlite>,
     identity: &Option<Identity>,
@@ -64,14 +121,20 @@ pub async fn check_for_user_from_identity(
     #[allow(clippy::unwrap_used)]
     let identity = identity.as_ref().unwrap();
 
-    let userid = identity
+    let login: LoginIdentity = identity
         .id()
-        .map_err(|_| anyhow!("Could not get user id"))?;
-    let user_uuid = Uuid::parse_str(&userid).map_err(|_| anyhow!("invalid userid"))?;
-    let id = sqlx::query_scalar!("SELECT id FROM users WHERE userid = $1", user_uuid)
-        .fetch_optional(conn)
-        .await
-        .map_err(|_| anyhow!("Could not get matching user"))?;
+        .map_err(|_| anyhow!("Could not get user id"))?
+        .parse()?;
+    let id = sqlx::query_scalar!(
+        "SELECT users.id FROM users
+        JOIN login_sessions ON login_sessions.user_id = users.id
+        WHERE userid = $1 AND login_sessions.session_id = $2",
+        login.userid,
+        login.session_id
+    )
+    .fetch_optional(conn)
+    .await
+    .map_err(|_| anyhow!("Could not get matching user"))?;
 
     if id.is_some() {
         Ok(())
@@ -97,11 +160,23 @@ pub async fn get_group_with_info(
         t.stages as "tone_stages: Json<Vec<String>>", 
         t.greeting, 
         t.unmet_behavior as "unmet_behavior: GoalBehavior", 
-        t.deadline as "deadline: DeadlineType"
+        t.deadline as "deadline: DeadlineType",
+        COALESCE(s.sort, 'manual') as "goal_sort!: GoalSort",
+        g.icon,
+        g.color as "color: LabelColor",
+        m.pinned as "pinned: bool",
+        m.role as "role: GroupRole",
+        (SELECT COUNT(*) FROM group_members o WHERE o.group_id = g.id) > 1 as "shared!: bool"
         FROM groups g
+        INNER JOIN group_members m
+        ON m.group_id = g.id AND m.user_id = $1
         LEFT JOIN tones t
         ON g.tone_id = t.id
-        WHERE g.user_id = $1 AND g.id = $2;"#,
+        LEFT JOIN group_goal_sorts s
+        ON s.group_id = g.id AND s.user_id = m.user_id
+        WHERE g.id = $2
+        AND g.deleted_at IS NULL
+        AND g.archived_at IS NULL;"#,
         user_id,
         group_id
     )
@@ -113,13 +188,147 @@ pub async fn get_group_with_info(
     })
 }
 
+/// Remember how a user wants the goals in a group sorted
+pub async fn set_goal_sort(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+    group_id: i64,
+    sort: GoalSort,
+) -> actix_web::Result<()> {
+    sqlx::query!(
+        "INSERT INTO group_goal_sorts(user_id, group_id, sort)
+        SELECT $1, group_id, $3 FROM group_members
+        WHERE group_id = $2 AND user_id = $1
+        ON CONFLICT(user_id, group_id) DO UPDATE SET sort = excluded.sort;",
+        user_id,
+        group_id,
+        sort
+    )
+    .execute(conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+    Ok(())
+}
+
 pub async fn get_group_links(
     conn: &mut PoolConnection<Sqlite>,
     user_id: i64,
 ) -> actix_web::Result<Vec<GroupLink>> {
     sqlx::query_as!(
         GroupLink,
-        "SELECT id, title FROM groups WHERE user_id = $1",
+        r#"SELECT g.id, g.title, g.icon, g.color as "color: LabelColor", m.pinned as "pinned: bool",
+        m.role as "role: GroupRole"
+        FROM groups g
+        INNER JOIN group_members m
+        ON m.group_id = g.id
+        WHERE m.user_id = $1 AND g.deleted_at IS NULL AND g.archived_at IS NULL
+        ORDER BY m.pinned DESC, m.position, g.id"#,
+        user_id
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+pub async fn get_groups(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+) -> actix_web::Result<Vec<Group>> {
+    sqlx::query_as!(
+        Group,
+        r#"SELECT g.id, g.title, g.description, g.user_id, g.tone_id, g.icon,
+        g.color as "color: LabelColor", m.pinned as "pinned: bool", m.role as "role: GroupRole"
+        FROM groups g
+        INNER JOIN group_members m
+        ON m.group_id = g.id
+        WHERE m.user_id = $1 AND g.deleted_at IS NULL AND g.archived_at IS NULL
+        ORDER BY m.pinned DESC, m.position, g.id"#,
+        user_id
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+/// Where a group that is new to the user's sidebar should go: at the end
+pub async fn get_next_group_position(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+) -> actix_web::Result<i64> {
+    sqlx::query_scalar!(
+        r#"SELECT COALESCE(MAX(position), 0) + 1 as "position!: i64" FROM group_members
+        WHERE user_id = $1;"#,
+        user_id
+    )
+    .fetch_one(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+/// The position after the last goal in a group, so new goals go at the end of
+/// the manual order
+pub async fn get_next_goal_position(
+    conn: &mut PoolConnection<Sqlite>,
+    group_id: i64,
+) -> actix_web::Result<i64> {
+    sqlx::query_scalar!(
+        r#"SELECT COALESCE(MAX(position), 0) + 1 as "position!: i64" FROM goals
+        WHERE group_id = $1;"#,
+        group_id
+    )
+    .fetch_one(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+pub async fn get_group(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+    group_id: i64,
+) -> actix_web::Result<Group> {
+    sqlx::query_as!(
+        Group,
+        r#"SELECT g.id, g.title, g.description, g.user_id, g.tone_id, g.icon,
+        g.color as "color: LabelColor", m.pinned as "pinned: bool", m.role as "role: GroupRole"
+        FROM groups g
+        INNER JOIN group_members m
+        ON m.group_id = g.id
+        WHERE m.user_id = $1 AND g.id = $2 AND g.deleted_at IS NULL AND g.archived_at IS NULL;"#,
+        user_id,
+        group_id
+    )
+    .fetch_one(conn)
+    .await
+    .map_err(|err| match err {
+        sqlx::Error::RowNotFound => ErrorNotFound(err),
+        e => ErrorInternalServerError(e),
+    })
+}
+
+pub async fn get_archived_groups(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+) -> actix_web::Result<Vec<ArchivedGroup>> {
+    sqlx::query_as!(
+        ArchivedGroup,
+        r#"SELECT
+        g.id,
+        g.title,
+        g.icon,
+        g.color as "color: LabelColor",
+        (SELECT COUNT(*) FROM goals go
+            WHERE go.group_id = g.id
+            AND go.deleted_at IS NULL
+            AND go.archived_at IS NULL) as "goal_count!: i64",
+        g.archived_at as "archived_at!: NaiveDateTime",
+        m.role as "role: GroupRole"
+        FROM groups g
+        INNER JOIN group_members m
+        ON m.group_id = g.id
+        WHERE m.user_id = $1
+        AND g.deleted_at IS NULL
+        AND g.archived_at IS NOT NULL
+        ORDER BY g.archived_at DESC, g.id;"#,
         user_id
     )
     .fetch_all(conn)
@@ -127,12 +336,781 @@ pub async fn get_group_links(
     .map_err(ErrorInternalServerError)
 }
 
+/// Like `get_group_with_info`, but only finds the group if it is archived
+pub async fn get_archived_group_with_info(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+    group_id: i64,
+) -> actix_web::Result<GroupWithInfo> {
+    sqlx::query_as!(
+        GroupWithInfo,
+        r#"SELECT 
+        g.id,
+        g.title, 
+        g.description, 
+        g.tone_id,
+        g.user_id,
+        t.name as tone_name, 
+        t.stages as "tone_stages: Json<Vec<String>>", 
+        t.greeting, 
+        t.unmet_behavior as "unmet_behavior: GoalBehavior", 
+        t.deadline as "deadline: DeadlineType",
+        COALESCE(s.sort, 'manual') as "goal_sort!: GoalSort",
+        g.icon,
+        g.color as "color: LabelColor",
+        m.pinned as "pinned: bool",
+        m.role as "role: GroupRole",
+        (SELECT COUNT(*) FROM group_members o WHERE o.group_id = g.id) > 1 as "shared!: bool"
+        FROM groups g
+        INNER JOIN group_members m
+        ON m.group_id = g.id AND m.user_id = $1
+        LEFT JOIN tones t
+        ON g.tone_id = t.id
+        LEFT JOIN group_goal_sorts s
+        ON s.group_id = g.id AND s.user_id = m.user_id
+        WHERE g.id = $2
+        AND g.deleted_at IS NULL
+        AND g.archived_at IS NOT NULL;"#,
+        user_id,
+        group_id
+    )
+    .fetch_one(conn)
+    .await
+    .map_err(|err| match err {
+        sqlx::Error::RowNotFound => ErrorNotFound(err),
+        e => ErrorInternalServerError(e),
+    })
+}
+
 pub async fn get_goals_for_group(
     conn: &mut PoolConnection<Sqlite>,
     group_id: i64,
 ) -> actix_web::Result<Vec<Goal>> {
-    sqlx::query_as!(Goal, "SELECT * FROM goals WHERE group_id = $1;", group_id)
-        .fetch_all(conn)
+    sqlx::query_as!(
+        Goal,
+        r#"SELECT
+        go.id,
+        go.title,
+        go.description,
+        go.stage,
+        go.group_id,
+        go.deadline,
+        go.priority as "priority: Priority",
+        go.updated_at as "updated_at: NaiveDateTime",
+        go.position,
+        (SELECT json_group_array(json_object('id', id, 'name', name, 'color', color))
+            FROM (SELECT l.id, l.name, l.color FROM goal_labels gl
+                INNER JOIN labels l
+                ON gl.label_id = l.id
+                WHERE gl.goal_id = go.id
+                ORDER BY l.name)) as "labels!: Json<Vec<Label>>",
+        COALESCE(cu.name, cu.email) as "created_by: String",
+        COALESCE(mu.name, mu.email) as "moved_by: String",
+        go.moved_at as "moved_at: NaiveDateTime"
+        FROM goals go
+        LEFT JOIN users cu
+        ON cu.id = go.created_by
+        LEFT JOIN users mu
+        ON mu.id = go.moved_by
+        WHERE go.group_id = $1
+        AND go.archived_at IS NULL
+        AND go.deleted_at IS NULL
+        ORDER BY go.id;"#,
+        group_id
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+pub async fn get_archived_goals_for_group(
+    conn: &mut PoolConnection<Sqlite>,
+    group_id: i64,
+) -> actix_web::Result<Vec<Goal>> {
+    sqlx::query_as!(
+        Goal,
+        r#"SELECT
+        go.id,
+        go.title,
+        go.description,
+        go.stage,
+        go.group_id,
+        go.deadline,
+        go.priority as "priority: Priority",
+        go.updated_at as "updated_at: NaiveDateTime",
+        go.position,
+        (SELECT json_group_array(json_object('id', id, 'name', name, 'color', color))
+            FROM (SELECT l.id, l.name, l.color FROM goal_labels gl
+                INNER JOIN labels l
+                ON gl.label_id = l.id
+                WHERE gl.goal_id = go.id
+                ORDER BY l.name)) as "labels!: Json<Vec<Label>>",
+        COALESCE(cu.name, cu.email) as "created_by: String",
+        COALESCE(mu.name, mu.email) as "moved_by: String",
+        go.moved_at as "moved_at: NaiveDateTime"
+        FROM goals go
+        LEFT JOIN users cu
+        ON cu.id = go.created_by
+        LEFT JOIN users mu
+        ON mu.id = go.moved_by
+        WHERE go.group_id = $1
+        AND go.archived_at IS NOT NULL
+        AND go.deleted_at IS NULL
+        ORDER BY go.archived_at DESC, go.id;"#,
+        group_id
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+pub async fn get_goal(
+    conn: &mut PoolConnection<Sqlite>,
+    group_id: i64,
+    goal_id: i64,
+) -> actix_web::Result<Goal> {
+    sqlx::query_as!(
+        Goal,
+        r#"SELECT
+        go.id,
+        go.title,
+        go.description,
+        go.stage,
+        go.group_id,
+        go.deadline,
+        go.priority as "priority: Priority",
+        go.updated_at as "updated_at: NaiveDateTime",
+        go.position,
+        (SELECT json_group_array(json_object('id', id, 'name', name, 'color', color))
+            FROM (SELECT l.id, l.name, l.color FROM goal_labels gl
+                INNER JOIN labels l
+                ON gl.label_id = l.id
+                WHERE gl.goal_id = go.id
+                ORDER BY l.name)) as "labels!: Json<Vec<Label>>",
+        COALESCE(cu.name, cu.email) as "created_by: String",
+        COALESCE(mu.name, mu.email) as "moved_by: String",
+        go.moved_at as "moved_at: NaiveDateTime"
+        FROM goals go
+        LEFT JOIN users cu
+        ON cu.id = go.created_by
+        LEFT JOIN users mu
+        ON mu.id = go.moved_by
+        WHERE go.id = $1 AND go.group_id = $2
+        AND go.deleted_at IS NULL;"#,
+        goal_id,
+        group_id
+    )
+    .fetch_one(conn)
+    .await
+    .map_err(|err| match err {
+        sqlx::Error::RowNotFound => ErrorNotFound(err),
+        e => ErrorInternalServerError(e),
+    })
+}
+
+pub async fn get_stage_transitions_for_group(
+    conn: &mut PoolConnection<Sqlite>,
+    group_id: i64,
+) -> actix_web::Result<Vec<StageTransition>> {
+    sqlx::query_as!(
+        StageTransition,
+        r#"SELECT
+        t.goal_id,
+        t.from_stage,
+        t.to_stage,
+        t.backfilled as "backfilled: bool",
+        t.created_at as "created_at: NaiveDateTime"
+        FROM goal_stage_transitions t
+        INNER JOIN goals g
+        ON t.goal_id = g.id
+        WHERE g.group_id = $1
+        AND g.deleted_at IS NULL
+        ORDER BY t.goal_id, t.created_at, t.id;"#,
+        group_id
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+/// Get unfinished goals with a deadline on or before `until` from every group
+/// the user has that uses deadlines, soonest first
+pub async fn get_upcoming_goals(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+    until: &str,
+) -> actix_web::Result<Vec<GoalWithGroup>> {
+    sqlx::query_as!(
+        GoalWithGroup,
+        r#"SELECT
+        go.id,
+        go.title,
+        go.description,
+        go.stage,
+        go.deadline as "deadline!: String",
+        go.group_id,
+        gr.title as group_title,
+        gr.icon as group_icon,
+        gr.color as "group_color: LabelColor",
+        t.stages as "tone_stages: Json<Vec<String>>",
+        (SELECT json_group_array(json_object('id', id, 'name', name, 'color', color))
+            FROM (SELECT l.id, l.name, l.color FROM goal_labels gl
+                INNER JOIN labels l
+                ON gl.label_id = l.id
+                WHERE gl.goal_id = go.id
+                ORDER BY l.name)) as "labels!: Json<Vec<Label>>"
+        FROM goals go
+        INNER JOIN groups gr
+        ON go.group_id = gr.id
+        INNER JOIN tones t
+        ON gr.tone_id = t.id
+        INNER JOIN group_members m
+        ON m.group_id = gr.id
+        WHERE m.user_id = $1
+        AND go.archived_at IS NULL
+        AND go.deleted_at IS NULL
+        AND gr.deleted_at IS NULL
+        AND gr.archived_at IS NULL
+        AND t.deadline != 'off'
+        AND go.deadline IS NOT NULL
+        AND go.deadline <= $2
+        AND go.stage < json_array_length(t.stages) - 1
+        ORDER BY go.deadline, gr.title, go.title;"#,
+        user_id,
+        until
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+/// Get goals with a deadline between `from` and `until`, inclusive, from the
+/// user's groups that use deadlines. Optionally limited to a single group.
+pub async fn get_goals_with_deadlines_between(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+    from: &str,
+    until: &str,
+    group_id: Option<i64>,
+) -> actix_web::Result<Vec<GoalWithGroup>> {
+    sqlx::query_as!(
+        GoalWithGroup,
+        r#"SELECT
+        go.id,
+        go.title,
+        go.description,
+        go.stage,
+        go.deadline as "deadline!: String",
+        go.group_id,
+        gr.title as group_title,
+        gr.icon as group_icon,
+        gr.color as "group_color: LabelColor",
+        t.stages as "tone_stages: Json<Vec<String>>",
+        (SELECT json_group_array(json_object('id', id, 'name', name, 'color', color))
+            FROM (SELECT l.id, l.name, l.color FROM goal_labels gl
+                INNER JOIN labels l
+                ON gl.label_id = l.id
+                WHERE gl.goal_id = go.id
+                ORDER BY l.name)) as "labels!: Json<Vec<Label>>"
+        FROM goals go
+        INNER JOIN groups gr
+        ON go.group_id = gr.id
+        INNER JOIN tones t
+        ON gr.tone_id = t.id
+        INNER JOIN group_members m
+        ON m.group_id = gr.id
+        WHERE m.user_id = $1
+        AND go.archived_at IS NULL
+        AND go.deleted_at IS NULL
+        AND gr.deleted_at IS NULL
+        AND gr.archived_at IS NULL
+        AND t.deadline != 'off'
+        AND go.deadline IS NOT NULL
+        AND go.deadline >= $2
+        AND go.deadline <= $3
+        AND ($4 IS NULL OR gr.id = $4)
+        ORDER BY go.deadline, go.stage, go.title;"#,
+        user_id,
+        from,
+        until,
+        group_id
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+/// Get every goal with a deadline from the user's groups that use deadlines
+pub async fn get_all_goals_with_deadlines(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+) -> actix_web::Result<Vec<GoalWithGroup>> {
+    sqlx::query_as!(
+        GoalWithGroup,
+        r#"SELECT
+        go.id,
+        go.title,
+        go.description,
+        go.stage,
+        go.deadline as "deadline!: String",
+        go.group_id,
+        gr.title as group_title,
+        gr.icon as group_icon,
+        gr.color as "group_color: LabelColor",
+        t.stages as "tone_stages: Json<Vec<String>>",
+        (SELECT json_group_array(json_object('id', id, 'name', name, 'color', color))
+            FROM (SELECT l.id, l.name, l.color FROM goal_labels gl
+                INNER JOIN labels l
+                ON gl.label_id = l.id
+                WHERE gl.goal_id = go.id
+                ORDER BY l.name)) as "labels!: Json<Vec<Label>>"
+        FROM goals go
+        INNER JOIN groups gr
+        ON go.group_id = gr.id
+        INNER JOIN tones t
+        ON gr.tone_id = t.id
+        INNER JOIN group_members m
+        ON m.group_id = gr.id
+        WHERE m.user_id = $1
+        AND go.archived_at IS NULL
+        AND go.deleted_at IS NULL
+        AND gr.deleted_at IS NULL
+        AND gr.archived_at IS NULL
+        AND t.deadline != 'off'
+        AND go.deadline IS NOT NULL
+        ORDER BY go.deadline, go.id;"#,
+        user_id
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+/// Turn what the user typed into an FTS5 query that matches every word as a
+/// prefix, so FTS syntax in the input can't cause errors
+pub fn fts_query(input: &str) -> Option<String> {
+    let terms = input
+        .split_whitespace()
+        .map(|word| word.replace('"', ""))
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"*", word))
+        .collect::<Vec<String>>();
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+pub async fn search_goals(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+    query: &str,
+) -> actix_web::Result<Vec<GoalSearchResult>> {
+    sqlx::query_as!(
+        GoalSearchResult,
+        r#"SELECT
+        go.id,
+        go.group_id,
+        gr.title as group_title,
+        highlight(goals_search, 0, char(2), char(3)) as "title!: String",
+        snippet(goals_search, 1, char(2), char(3), '…', 12) as "snippet: String"
+        FROM goals_search
+        INNER JOIN goals go
+        ON go.id = goals_search.rowid
+        INNER JOIN groups gr
+        ON go.group_id = gr.id
+        INNER JOIN group_members m
+        ON m.group_id = gr.id
+        WHERE goals_search MATCH $1
+        AND m.user_id = $2
+        AND go.archived_at IS NULL
+        AND go.deleted_at IS NULL
+        AND gr.deleted_at IS NULL
+        AND gr.archived_at IS NULL
+        ORDER BY rank
+        LIMIT 20;"#,
+        query,
+        user_id
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+pub async fn search_groups(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+    query: &str,
+) -> actix_web::Result<Vec<GroupSearchResult>> {
+    sqlx::query_as!(
+        GroupSearchResult,
+        r#"SELECT
+        gr.id,
+        highlight(groups_search, 0, char(2), char(3)) as "title!: String",
+        snippet(groups_search, 1, char(2), char(3), '…', 12) as "snippet: String"
+        FROM groups_search
+        INNER JOIN groups gr
+        ON gr.id = groups_search.rowid
+        INNER JOIN group_members m
+        ON m.group_id = gr.id
+        WHERE groups_search MATCH $1
+        AND m.user_id = $2
+        AND gr.deleted_at IS NULL
+        AND gr.archived_at IS NULL
+        ORDER BY rank
+        LIMIT 5;"#,
+        query,
+        user_id
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+pub async fn get_labels(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+) -> actix_web::Result<Vec<Label>> {
+    sqlx::query_as!(
+        Label,
+        r#"SELECT id, name, color as "color: LabelColor" FROM labels
+        WHERE user_id = $1
+        ORDER BY name;"#,
+        user_id
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+/// Replace the labels on a goal, skipping any labels the user doesn't own
+pub async fn set_goal_labels(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+    goal_id: i64,
+    label_ids: &[i64],
+) -> actix_web::Result<()> {
+    // Labels are per user, so members of a shared group keep theirs
+    sqlx::query!(
+        "DELETE FROM goal_labels
+        WHERE goal_id = $1 AND label_id IN (SELECT id FROM labels WHERE user_id = $2);",
+        goal_id,
+        user_id
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    for label_id in label_ids {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO goal_labels(goal_id, label_id)
+            SELECT $1, id FROM labels WHERE id = $2 AND user_id = $3;",
+            goal_id,
+            label_id,
+            user_id
+        )
+        .execute(&mut *conn)
         .await
-        .map_err(ErrorInternalServerError)
+        .map_err(ErrorInternalServerError)?;
+    }
+    Ok(())
+}
+
+/// Get deleted groups the user owns, as only owners can delete and restore them
+pub async fn get_deleted_groups(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+    retention: TrashRetention,
+) -> actix_web::Result<Vec<DeletedGroup>> {
+    sqlx::query_as!(
+        DeletedGroup,
+        r#"SELECT
+        g.id,
+        g.title,
+        (SELECT COUNT(*) FROM goals go
+            WHERE go.group_id = g.id AND go.deleted_at IS NULL) as "goal_count!: i64",
+        g.deleted_at as "deleted_at!: NaiveDateTime",
+        date(g.deleted_at, '+' || $2 || ' days') as "purge_on!: NaiveDate"
+        FROM groups g
+        INNER JOIN group_members m
+        ON m.group_id = g.id
+        WHERE m.user_id = $1
+        AND m.role = 'owner'
+        AND g.deleted_at IS NOT NULL
+        ORDER BY g.deleted_at DESC, g.id;"#,
+        user_id,
+        retention.0
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+/// Get deleted goals from groups the user can edit that are not deleted
+/// themselves. Goals in a deleted group come back with the group.
+pub async fn get_deleted_goals(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+    retention: TrashRetention,
+) -> actix_web::Result<Vec<DeletedGoal>> {
+    sqlx::query_as!(
+        DeletedGoal,
+        r#"SELECT
+        go.id,
+        go.title,
+        go.group_id,
+        gr.title as group_title,
+        go.deleted_at as "deleted_at!: NaiveDateTime",
+        date(go.deleted_at, '+' || $2 || ' days') as "purge_on!: NaiveDate"
+        FROM goals go
+        INNER JOIN groups gr
+        ON go.group_id = gr.id
+        INNER JOIN group_members m
+        ON m.group_id = gr.id
+        WHERE m.user_id = $1
+        AND m.role != 'viewer'
+        AND gr.deleted_at IS NULL
+        AND go.deleted_at IS NOT NULL
+        ORDER BY go.deleted_at DESC, go.id;"#,
+        user_id,
+        retention.0
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+/// Permanently delete goals and groups that have been in the trash longer
+/// than the retention period, returning how many were removed
+pub async fn purge_trash(pool: &SqlitePool, retention: TrashRetention) -> sqlx::Result<u64> {
+    let goals = sqlx::query!(
+        "DELETE FROM goals WHERE deleted_at < datetime('now', '-' || $1 || ' days');",
+        retention.0
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    let groups = sqlx::query!(
+        "DELETE FROM groups WHERE deleted_at < datetime('now', '-' || $1 || ' days');",
+        retention.0
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(goals + groups)
+}
+
+pub async fn get_group_members(
+    conn: &mut PoolConnection<Sqlite>,
+    group_id: i64,
+) -> actix_web::Result<Vec<GroupMember>> {
+    sqlx::query_as!(
+        GroupMember,
+        r#"SELECT u.id as user_id, u.name, u.email, m.role as "role: GroupRole"
+        FROM group_members m
+        INNER JOIN users u
+        ON u.id = m.user_id
+        WHERE m.group_id = $1
+        ORDER BY m.role = 'owner' DESC, m.created_at, u.id;"#,
+        group_id
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+/// Invitations to a group that haven't been answered or run out yet
+pub async fn get_group_invitations(
+    conn: &mut PoolConnection<Sqlite>,
+    group_id: i64,
+) -> actix_web::Result<Vec<GroupInvitation>> {
+    sqlx::query_as!(
+        GroupInvitation,
+        r#"SELECT id, email, role as "role: GroupRole", created_at as "created_at: NaiveDateTime"
+        FROM group_invitations
+        WHERE group_id = $1
+        AND created_at > datetime('now', '-' || $2 || ' days')
+        ORDER BY created_at, id;"#,
+        group_id,
+        INVITATION_DAYS
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+/// Invitations waiting for whoever owns `email`
+pub async fn get_pending_invitations(
+    conn: &mut PoolConnection<Sqlite>,
+    email: &str,
+) -> actix_web::Result<Vec<PendingInvitation>> {
+    sqlx::query_as!(
+        PendingInvitation,
+        r#"SELECT
+        i.id,
+        i.group_id,
+        g.title as group_title,
+        i.email,
+        i.role as "role: GroupRole",
+        COALESCE(u.name, u.email) as "invited_by!: String"
+        FROM group_invitations i
+        INNER JOIN groups g
+        ON g.id = i.group_id
+        INNER JOIN users u
+        ON u.id = i.invited_by
+        WHERE i.email = Lower($1)
+        AND i.created_at > datetime('now', '-' || $2 || ' days')
+        AND g.deleted_at IS NULL
+        AND g.archived_at IS NULL
+        ORDER BY i.created_at, i.id;"#,
+        email,
+        INVITATION_DAYS
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+pub async fn get_invitation(
+    conn: &mut PoolConnection<Sqlite>,
+    token_hash: &str,
+) -> actix_web::Result<PendingInvitation> {
+    sqlx::query_as!(
+        PendingInvitation,
+        r#"SELECT
+        i.id,
+        i.group_id,
+        g.title as group_title,
+        i.email,
+        i.role as "role: GroupRole",
+        COALESCE(u.name, u.email) as "invited_by!: String"
+        FROM group_invitations i
+        INNER JOIN groups g
+        ON g.id = i.group_id
+        INNER JOIN users u
+        ON u.id = i.invited_by
+        WHERE i.token_hash = $1
+        AND i.created_at > datetime('now', '-' || $2 || ' days')
+        AND g.deleted_at IS NULL
+        AND g.archived_at IS NULL;"#,
+        token_hash,
+        INVITATION_DAYS
+    )
+    .fetch_optional(conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .ok_or_else(|| ErrorNotFound("This invitation has expired or was cancelled"))
+}
+
+/// Get the invitation to a group waiting for whoever owns `email`
+pub async fn get_group_invitation(
+    conn: &mut PoolConnection<Sqlite>,
+    group_id: i64,
+    email: &str,
+) -> actix_web::Result<PendingInvitation> {
+    sqlx::query_as!(
+        PendingInvitation,
+        r#"SELECT
+        i.id,
+        i.group_id,
+        g.title as group_title,
+        i.email,
+        i.role as "role: GroupRole",
+        COALESCE(u.name, u.email) as "invited_by!: String"
+        FROM group_invitations i
+        INNER JOIN groups g
+        ON g.id = i.group_id
+        INNER JOIN users u
+        ON u.id = i.invited_by
+        WHERE i.group_id = $1
+        AND i.email = Lower($2)
+        AND i.created_at > datetime('now', '-' || $3 || ' days')
+        AND g.deleted_at IS NULL
+        AND g.archived_at IS NULL;"#,
+        group_id,
+        email,
+        INVITATION_DAYS
+    )
+    .fetch_optional(conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .ok_or_else(|| ErrorNotFound("This invitation has expired or was cancelled"))
+}
+
+pub async fn get_share_links(
+    conn: &mut PoolConnection<Sqlite>,
+    group_id: i64,
+) -> actix_web::Result<Vec<ShareLink>> {
+    sqlx::query_as!(
+        ShareLink,
+        r#"SELECT
+        id,
+        token,
+        hide_descriptions as "hide_descriptions: bool",
+        expires_at as "expires_at: NaiveDateTime",
+        created_at as "created_at: NaiveDateTime"
+        FROM share_links
+        WHERE group_id = $1
+        ORDER BY created_at DESC, id DESC;"#,
+        group_id
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+/// Find the group a share link points to, as long as the link hasn't expired
+/// and the group, and its owner's account, are still out in the open
+pub async fn get_shared_group(
+    conn: &mut PoolConnection<Sqlite>,
+    token: &str,
+) -> actix_web::Result<SharedGroup> {
+    sqlx::query_as!(
+        SharedGroup,
+        r#"SELECT
+        s.group_id,
+        g.user_id as owner_id,
+        s.hide_descriptions as "hide_descriptions: bool"
+        FROM share_links s
+        INNER JOIN groups g
+        ON g.id = s.group_id
+        INNER JOIN users u
+        ON u.id = g.user_id
+        WHERE s.token = $1
+        AND (s.expires_at IS NULL OR s.expires_at > CURRENT_TIMESTAMP)
+        AND g.deleted_at IS NULL
+        AND g.archived_at IS NULL
+        AND u.deletion_scheduled_for IS NULL;"#,
+        token
+    )
+    .fetch_optional(conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .ok_or_else(|| ErrorNotFound("This link has expired or was turned off"))
+}
+
+pub async fn get_login_sessions(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+) -> actix_web::Result<Vec<LoginSession>> {
+    sqlx::query_as!(
+        LoginSession,
+        r#"SELECT id, session_id, method as "method: LoginMethod", user_agent,
+            created_at as "created_at: NaiveDateTime",
+            last_seen_at as "last_seen_at: NaiveDateTime"
+        FROM login_sessions
+        WHERE user_id = $1
+        ORDER BY last_seen_at DESC"#,
+        user_id
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(|err| {
+        error!("Error communicating with database: {}", err);
+        ErrorInternalServerError(err)
+    })
 }
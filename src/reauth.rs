@@ -0,0 +1,96 @@
+use actix_identity::Identity;
+use actix_web::{
+    error::{ErrorInternalServerError, InternalError},
+    http::Method,
+    HttpRequest, HttpResponse,
+};
+use sqlx::{pool::PoolConnection, Sqlite};
+use webauthn_rs::prelude::Url;
+
+use crate::login_sessions::LoginIdentity;
+
+/// How long after logging in, or confirming it's them, a user can make
+/// sensitive changes to their account without being asked again
+pub const RECENT_AUTH_MINUTES: i64 = 10;
+
+/// Only send users back to a page on this site after they confirm it's them
+pub fn safe_next(next: Option<&str>) -> String {
+    match next {
+        Some(next) if next.starts_with('/') && !next.starts_with("//") && !next.contains('\\') => {
+            next.to_owned()
+        }
+        _ => "/profile".into(),
+    }
+}
+
+/// Where to come back to after confirming. Pages can be reloaded, but for
+/// anything else the user goes back to the page they were on and tries again.
+fn next_for_request(req: &HttpRequest) -> String {
+    let is_hx = req.headers().contains_key("HX-Request");
+    if req.method() == Method::GET && !is_hx {
+        return safe_next(Some(req.uri().path()));
+    }
+    let current_url = req
+        .headers()
+        .get("HX-Current-URL")
+        .and_then(|url| url.to_str().ok())
+        .and_then(|url| Url::parse(url).ok());
+    safe_next(current_url.as_ref().map(|url| url.path()))
+}
+
+/// Guard for sensitive account changes. Unless the user proved who they are in
+/// the last few minutes, the request is turned away and they are sent to
+/// confirm with a passkey or an emailed code first.
+pub async fn require_recent_auth(
+    conn: &mut PoolConnection<Sqlite>,
+    req: &HttpRequest,
+    identity: &Identity,
+) -> actix_web::Result<()> {
+    let login_identity = LoginIdentity::from_identity(identity)?;
+    let recent = sqlx::query_scalar!(
+        "SELECT id FROM login_sessions
+        WHERE session_id = $1
+        AND authenticated_at > datetime('now', '-' || $2 || ' minutes');",
+        login_identity.session_id,
+        RECENT_AUTH_MINUTES
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .is_some();
+
+    if recent {
+        return Ok(());
+    }
+
+    let query = serde_html_form::to_string([("next", next_for_request(req))])
+        .map_err(ErrorInternalServerError)?;
+    let location = format!("/reauth?{query}");
+    let response = if req.method() == Method::GET && !req.headers().contains_key("HX-Request") {
+        HttpResponse::SeeOther()
+            .insert_header(("Location", location))
+            .finish()
+    } else {
+        // htmx follows the redirect header, and so does the account deletion script
+        HttpResponse::Forbidden()
+            .insert_header(("HX-Redirect", location))
+            .finish()
+    };
+    Err(InternalError::from_response("Recent authentication required", response).into())
+}
+
+/// Record that the user just proved who they are in this login session
+pub async fn mark_authenticated(
+    conn: &mut PoolConnection<Sqlite>,
+    identity: &Identity,
+) -> actix_web::Result<()> {
+    let login_identity = LoginIdentity::from_identity(identity)?;
+    sqlx::query!(
+        "UPDATE login_sessions SET authenticated_at = CURRENT_TIMESTAMP WHERE session_id = $1;",
+        login_identity.session_id
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use actix_web::error::ErrorInternalServerError;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use sqlx::{pool::PoolConnection, Sqlite};
+
+use crate::{login_links::hash_token, SessionValue};
+
+/// How many codes a user gets at a time
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Crockford's base32, which leaves out letters that look like numbers
+const ALPHABET: &[u8] = b"0123456789abcdefghjkmnpqrstvwxyz";
+
+/// 16 characters is 80 random bits, too many to guess even without a limit
+const CODE_LENGTH: usize = 16;
+
+fn new_recovery_code() -> String {
+    let mut rng = thread_rng();
+    let code: String = (0..CODE_LENGTH)
+        .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+        .collect();
+    // Shown in groups of four, which is easier to copy down
+    code.as_bytes()
+        .chunks(4)
+        .map(|chunk| String::from_utf8_lossy(chunk))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Put a typed code in the form it was hashed in, forgiving case, spaces,
+/// dashes and the letters that are easy to mistake for numbers
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| match c.to_ascii_lowercase() {
+            'o' => '0',
+            'i' | 'l' => '1',
+            c => c,
+        })
+        .collect()
+}
+
+/// Replace all of a user's recovery codes with new ones, returning them. This
+/// is the only time they can be seen.
+pub async fn new_recovery_codes(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+) -> actix_web::Result<Vec<String>> {
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1;", user_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| new_recovery_code())
+        .collect();
+    for code in &codes {
+        let code_hash = hash_token(&normalize_recovery_code(code));
+        sqlx::query!(
+            "INSERT INTO recovery_codes(user_id, code_hash) VALUES ($1, $2);",
+            user_id,
+            code_hash
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    }
+
+    Ok(codes)
+}
+
+/// How many of the user's recovery codes haven't been used yet
+pub async fn recovery_codes_left(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+) -> actix_web::Result<i64> {
+    sqlx::query_scalar!(
+        r#"SELECT COUNT(id) as "count!: i64" FROM recovery_codes
+        WHERE user_id = $1 AND used_at IS NULL;"#,
+        user_id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
+
+/// Codes made when the first passkey was registered, kept in the session only
+/// until the profile shows them
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NewRecoveryCodes(pub Vec<String>);
+
+impl SessionValue for NewRecoveryCodes {
+    fn save_name() -> &'static str {
+        "new_recovery_codes"
+    }
+}
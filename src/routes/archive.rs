@@ -0,0 +1,184 @@
+use actix_identity::Identity;
+use actix_web::{
+    error::{ErrorForbidden, ErrorInternalServerError, ErrorNotFound},
+    get, patch, web, HttpResponse,
+};
+use askama::Template;
+use sqlx::SqlitePool;
+
+use crate::{
+    htmx::{hx_trigger_notification, HxHeaderInfo, IsHtmx, NotificationVariant},
+    members::{GroupRole, Permission},
+    queries,
+    templates::*,
+    GroupDisplay,
+};
+
+/// Groups that have been put away, with their goals kept as they were
+#[get("/archive")]
+async fn archive(
+    identity: Identity,
+    pool: web::Data<SqlitePool>,
+    is_hx: IsHtmx,
+    hx_headers: HxHeaderInfo,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let archived_groups = queries::get_archived_groups(&mut conn, user.id).await?;
+
+    let body = if *is_hx && !hx_headers.boosted {
+        ArchivePartial { archived_groups }
+            .render()
+            .map_err(ErrorInternalServerError)?
+    } else {
+        let groups = queries::get_group_links(&mut conn, user.id).await?;
+        ArchivePage {
+            title: "Silly Goals".into(),
+            user,
+            groups,
+            archived_groups,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?
+    };
+    Ok(HttpResponse::Ok()
+        .insert_header(("HX-Trigger-After-Swap", "updateLocation"))
+        .body(body))
+}
+
+/// Look at an archived group and its goals without being able to change them
+#[get("/archive/groups/{id}")]
+async fn archived_group(
+    identity: Identity,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+    is_hx: IsHtmx,
+    hx_headers: HxHeaderInfo,
+) -> actix_web::Result<HttpResponse> {
+    let group_id = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let group: GroupDisplay = queries::get_archived_group_with_info(&mut conn, user.id, group_id)
+        .await?
+        .into();
+    let mut goals = queries::get_goals_for_group(&mut conn, group_id).await?;
+    group.goal_sort.sort(&mut goals);
+    goals.sort_by_key(|goal| goal.stage);
+
+    let body = if *is_hx && !hx_headers.boosted {
+        ArchivedGroupPartial { group, goals }
+            .render()
+            .map_err(ErrorInternalServerError)?
+    } else {
+        let groups = queries::get_group_links(&mut conn, user.id).await?;
+        ArchivedGroupPage {
+            title: "Silly Goals".into(),
+            user,
+            groups,
+            group,
+            goals,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?
+    };
+    Ok(HttpResponse::Ok()
+        .insert_header(("HX-Trigger-After-Swap", "updateLocation"))
+        .body(body))
+}
+
+/// Put a group away. It drops out of the sidebar, dashboard and every list of
+/// goals until it is restored.
+#[patch("/groups/{id}/archive")]
+async fn archive_group(
+    identity: Identity,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let group_id = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let group = queries::get_group(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::ManageGroup)?;
+
+    sqlx::query!(
+        "UPDATE groups SET archived_at = CURRENT_TIMESTAMP
+        WHERE id = $1 AND deleted_at IS NULL AND archived_at IS NULL;",
+        group_id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Bring an archived group back to the dashboard and sidebar
+#[patch("/archive/groups/{id}/restore")]
+async fn restore_group(
+    identity: Identity,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let group_id = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let role = sqlx::query_scalar!(
+        r#"SELECT role as "role: GroupRole" FROM group_members WHERE group_id = $1 AND user_id = $2;"#,
+        group_id,
+        user.id
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .ok_or_else(|| ErrorNotFound("Group is not archived"))?;
+
+    if !role.can_manage() {
+        return Err(ErrorForbidden("Only the owner can restore a group"));
+    }
+
+    let restored = sqlx::query!(
+        "UPDATE groups SET archived_at = NULL
+        WHERE id = $1 AND deleted_at IS NULL AND archived_at IS NOT NULL;",
+        group_id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .rows_affected();
+
+    if restored == 0 {
+        return Err(ErrorNotFound("Group is not archived"));
+    }
+
+    let notification = hx_trigger_notification(
+        "Group Restored".into(),
+        "Your group is back on the dashboard".into(),
+        NotificationVariant::Success,
+        true,
+    );
+
+    Ok(HttpResponse::Ok()
+        .append_header(notification)
+        .insert_header(("HX-Trigger", "groupsChanged"))
+        .finish())
+}
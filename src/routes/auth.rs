@@ -1,8 +1,15 @@
 use crate::{
+    account_deletion::{new_restore_link, DeletionGracePeriod},
     csrf_token::CsrfToken,
+    devices::{notify_new_device, remember_device, Device},
+    email_limits::{check_email_limits, wait_message, EmailKind, EmailLimits, PowChallenge},
     htmx::{self, IsHtmx},
+    login_links::{hash_token, new_login_link_token, LOGIN_LINK_MINUTES},
+    login_sessions::{self, LoginIdentity, LoginMethod},
     mail::*,
     queries,
+    reauth::require_recent_auth,
+    security_events::{record_security_event, SecurityEventKind},
     session_values::*,
     templates::*,
     SessionValue, User,
@@ -10,20 +17,28 @@ use crate::{
 use actix_identity::Identity;
 use actix_session::Session;
 use actix_web::{
-    error::ErrorInternalServerError,
+    error::{ErrorConflict, ErrorInternalServerError},
     web::{self, Form},
     *,
 };
 use anyhow::anyhow;
 use askama::Template;
+use chrono::Utc;
 use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
 use log::{error, info};
 use serde::Deserialize;
 use sqlx::{pool::PoolConnection, types::Uuid, Sqlite, SqlitePool};
 
+/// How long the old address can undo an email change for
+const EMAIL_CHANGE_REVERT_DAYS: i64 = 7;
+
 /// Start Registration for the user account
 #[get("register")]
-async fn register(session: Session, identity: Option<Identity>) -> Result<HttpResponse> {
+async fn register(
+    session: Session,
+    identity: Option<Identity>,
+    limits: web::Data<EmailLimits>,
+) -> Result<HttpResponse> {
     if identity.is_some() {
         return Ok(HttpResponse::SeeOther()
             .insert_header(("Location", "/profile"))
@@ -36,6 +51,9 @@ async fn register(session: Session, identity: Option<Identity>) -> Result<HttpRe
     let body = RegisterStart {
         title: "Register . Silly Goals".into(),
         csrf_token,
+        pow_challenge: PowChallenge::issue(&session, &limits)?,
+        pow_bits: limits.proof_of_work_bits,
+        wait_minutes: None,
     }
     .render()
     .map_err(ErrorInternalServerError)?;
@@ -47,17 +65,21 @@ async fn register(session: Session, identity: Option<Identity>) -> Result<HttpRe
 pub struct RegistrationForm {
     pub email: String,
     pub csrftoken: String,
+    pub pow_nonce: Option<String>,
 }
 
 /// Receive email from user form and send back otp code form
 #[post("register")]
 async fn post_register(
+    req: HttpRequest,
     session: Session,
     form: Form<RegistrationForm>,
     pool: web::Data<SqlitePool>,
     mailer: web::Data<AsyncSmtpTransport<Tokio1Executor>>,
-) -> Result<RegisterFinish> {
+    limits: web::Data<EmailLimits>,
+) -> Result<HttpResponse> {
     CsrfToken::verify_from_session(&session, form.csrftoken.as_str())?;
+    PowChallenge::verify_from_session(&session, &limits, form.pow_nonce.as_deref())?;
     LoginCode::remove(&session);
     LoginEmail::remove(&session);
     RegistrationEmail::remove(&session);
@@ -68,6 +90,21 @@ async fn post_register(
         .await
         .map_err(|err| ErrorInternalServerError(err))?;
 
+    if let Some(wait_minutes) =
+        check_email_limits(&mut conn, &req, &limits, EmailKind::Register, &form.email).await?
+    {
+        let body = RegisterStart {
+            title: "Register . Silly Goals".into(),
+            csrf_token: CsrfToken::get_or_create(&session)?,
+            pow_challenge: PowChallenge::issue(&session, &limits)?,
+            pow_bits: limits.proof_of_work_bits,
+            wait_minutes: Some(wait_minutes),
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?;
+        return Ok(HttpResponse::TooManyRequests().body(body));
+    }
+
     let existing_user_count = sqlx::query_scalar!(
         "SELECT COUNT(id)
             FROM users
@@ -117,11 +154,15 @@ async fn post_register(
         }
     });
 
-    Ok(RegisterFinish {
+    let body = RegisterFinish {
         title: "Register . Silly Goals".into(),
         csrf_token,
         error: None,
-    })
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().body(body))
 }
 
 #[derive(Deserialize)]
@@ -219,17 +260,27 @@ async fn finish_registration(
         ErrorInternalServerError(err)
     })?;
 
-    Identity::login(&req.extensions(), new_user.userid.to_string()).map_err(|err| {
-        error!("Error Logging in new user: {}", err);
-        ErrorInternalServerError(err)
-    })?;
+    login_sessions::login(
+        &mut conn,
+        &req,
+        &session,
+        new_user.userid,
+        LoginMethod::Code,
+    )
+    .await?;
+
+    // No need to warn about the device someone registered with
+    let device = Device::from_request(&req);
+    remember_device(&mut conn, new_user.id, &device).await?;
 
     RegistrationEmail::remove(&session);
     LoginCode::remove(&session);
 
-    Ok(HttpResponse::SeeOther()
-        .insert_header(("Location", "/dashboard"))
-        .finish())
+    let mut response = HttpResponse::SeeOther();
+    if let Some(cookie) = device.new_cookie {
+        response.cookie(cookie);
+    }
+    Ok(response.insert_header(("Location", "/dashboard")).finish())
 }
 
 /// Start Login for the user account
@@ -238,6 +289,7 @@ async fn login(
     session: Session,
     identity: Option<Identity>,
     pool: web::Data<SqlitePool>,
+    limits: web::Data<EmailLimits>,
 ) -> Result<HttpResponse> {
     let mut conn = pool
         .get_ref()
@@ -270,6 +322,9 @@ async fn login(
     let body = LoginStart {
         title: "Login . Silly Goals".into(),
         csrf_token,
+        pow_challenge: PowChallenge::issue(&session, &limits)?,
+        pow_bits: limits.proof_of_work_bits,
+        wait_minutes: None,
     }
     .render()
     .map_err(ErrorInternalServerError)?;
@@ -281,17 +336,22 @@ async fn login(
 pub struct LoginForm {
     pub email: String,
     pub csrftoken: String,
+    pub pow_nonce: Option<String>,
 }
 
 /// Receive email from user form and send back login selection
 #[post("login")]
 async fn post_login(
+    req: HttpRequest,
     session: Session,
     form: Form<LoginForm>,
     pool: web::Data<SqlitePool>,
     mailer: web::Data<AsyncSmtpTransport<Tokio1Executor>>,
+    hostname: web::Data<String>,
+    limits: web::Data<EmailLimits>,
 ) -> Result<HttpResponse> {
     CsrfToken::verify_from_session(&session, form.csrftoken.as_str())?;
+    PowChallenge::verify_from_session(&session, &limits, form.pow_nonce.as_deref())?;
     LoginCode::remove(&session);
     LoginEmail::remove(&session);
     RegistrationEmail::remove(&session);
@@ -302,6 +362,23 @@ async fn post_login(
         .await
         .map_err(|err| ErrorInternalServerError(err))?;
 
+    // Counted whether or not an email goes out, so being turned away doesn't
+    // give away which addresses have accounts
+    if let Some(wait_minutes) =
+        check_email_limits(&mut conn, &req, &limits, EmailKind::Login, &form.email).await?
+    {
+        let body = LoginStart {
+            title: "Login . Silly Goals".into(),
+            csrf_token: CsrfToken::get_or_create(&session)?,
+            pow_challenge: PowChallenge::issue(&session, &limits)?,
+            pow_bits: limits.proof_of_work_bits,
+            wait_minutes: Some(wait_minutes),
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?;
+        return Ok(HttpResponse::TooManyRequests().body(body));
+    }
+
     let user = sqlx::query_as!(
         User,
         r#"SELECT id, userid as "userid: Uuid", name, email, is_new_user
@@ -313,7 +390,43 @@ async fn post_login(
     .await
     .map_err(ErrorInternalServerError)?;
 
-    if let Some(user) = user {
+    let deletion_scheduled = match &user {
+        Some(user) => {
+            sqlx::query_scalar!(
+                "SELECT deletion_scheduled_for IS NOT NULL FROM users WHERE id = $1",
+                user.id
+            )
+            .fetch_one(&mut conn)
+            .await
+            .map_err(ErrorInternalServerError)?
+                != 0
+        }
+        None => false,
+    };
+
+    if let Some(user) = user.as_ref().filter(|_| deletion_scheduled) {
+        // The account is locked, but they may want it back and have lost the
+        // email with the restore link
+        let restore_url = new_restore_link(&mut conn, &hostname, user.id).await?;
+        let message = build_email_for_user(
+            &user.email,
+            "Login Attempt at Silly Goals",
+            &format!(
+                "Someone tried to log in to your Silly Goals account, but it's \
+                waiting to be deleted so they couldn't. If it was you and you \
+                want to keep your account, open this link to restore it: {restore_url}"
+            ),
+        )?;
+
+        tokio::spawn(async move {
+            match mailer.send(message).await {
+                Ok(_) => (),
+                Err(e) => {
+                    error!("Could not send locked account message: {}", e);
+                }
+            }
+        });
+    } else if let Some(user) = user {
         let login_email = LoginEmail::from(&user.email);
         login_email.save(&session)?;
     } else {
@@ -347,29 +460,30 @@ async fn post_login(
 
 #[get("/login-code")]
 async fn login_with_code(
+    req: HttpRequest,
     session: Session,
+    pool: web::Data<SqlitePool>,
     mailer: web::Data<AsyncSmtpTransport<Tokio1Executor>>,
+    hostname: web::Data<String>,
+    limits: web::Data<EmailLimits>,
 ) -> actix_web::Result<HttpResponse> {
     let login_email = LoginEmail::get(&session).map_err(ErrorInternalServerError)?;
+    let mut error = None;
 
     if let Some(login_email) = login_email {
-        let login_code = LoginCode::new();
-        login_code.save(&session)?;
-
-        let message = build_email_for_user(
-            &login_email,
-            "Login Code for Silly Goals",
-            &format!("Use code {login_code} to log in to your account."),
-        )?;
+        let mut conn = pool
+            .get_ref()
+            .acquire()
+            .await
+            .map_err(ErrorInternalServerError)?;
 
-        tokio::spawn(async move {
-            match mailer.send(message).await {
-                Ok(_) => (),
-                Err(e) => {
-                    error!("Could not sent message: {}", e);
-                }
-            }
-        });
+        if let Some(wait_minutes) =
+            check_email_limits(&mut conn, &req, &limits, EmailKind::LoginCode, &login_email).await?
+        {
+            error = Some(wait_message(wait_minutes));
+        } else {
+            send_login_code(&mut conn, &session, &mailer, &hostname, &login_email).await?;
+        }
     }
 
     let csrf_token = CsrfToken::get_or_create(&session)?;
@@ -377,7 +491,7 @@ async fn login_with_code(
     let body = LoginFinish {
         title: "Login . Silly Goals".into(),
         csrf_token,
-        error: None,
+        error,
     }
     .render()
     .map_err(ErrorInternalServerError)?;
@@ -385,6 +499,168 @@ async fn login_with_code(
     Ok(HttpResponse::Ok().body(body))
 }
 
+/// Email a login code, and a link that works on any device
+async fn send_login_code(
+    conn: &mut PoolConnection<Sqlite>,
+    session: &Session,
+    mailer: &web::Data<AsyncSmtpTransport<Tokio1Executor>>,
+    hostname: &str,
+    login_email: &str,
+) -> actix_web::Result<()> {
+    let login_code = LoginCode::new();
+    login_code.save(session)?;
+
+    let user = queries::get_user_by_email(conn, login_email).await?;
+
+    // The link is checked against the database rather than the session,
+    // so it can be opened on a different device than the one logging in
+    let token = new_login_link_token();
+    let token_hash = hash_token(&token);
+    sqlx::query!(
+        "INSERT INTO login_links(user_id, token_hash, expires_at)
+        VALUES ($1, $2, datetime('now', '+' || $3 || ' minutes'));",
+        user.id,
+        token_hash,
+        LOGIN_LINK_MINUTES
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    let message = build_email_for_user(
+        login_email,
+        "Login Code for Silly Goals",
+        &format!(
+            "Use code {login_code} to log in to your account.\n\n\
+            Or open this link to log in, on any device: https://{}/login-link/{}\n\n\
+            The link works once, for the next {} minutes.",
+            hostname, token, LOGIN_LINK_MINUTES
+        ),
+    )?;
+
+    let mailer = mailer.clone();
+    tokio::spawn(async move {
+        match mailer.send(message).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!("Could not sent message: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Confirm before logging in with an emailed link. Email scanners open links
+/// to check them, so a GET can't be what uses the link up.
+#[get("/login-link/{token}")]
+async fn login_link(
+    session: Session,
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let token = path.into_inner();
+    let token_hash = hash_token(&token);
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let valid = sqlx::query_scalar!(
+        "SELECT id FROM login_links
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > CURRENT_TIMESTAMP",
+        token_hash
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .is_some();
+
+    let csrf_token = CsrfToken::get_or_create(&session)?;
+    let body = LoginLinkPage {
+        title: "Login . Silly Goals".into(),
+        token,
+        valid,
+        csrf_token,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(("X-Robots-Tag", "noindex, nofollow"))
+        .insert_header(("Referrer-Policy", "no-referrer"))
+        .body(body))
+}
+
+#[derive(Deserialize)]
+struct LoginLinkForm {
+    pub csrftoken: String,
+}
+
+/// Use up an emailed login link and log in
+#[post("/login-link/{token}")]
+async fn finish_login_link(
+    req: HttpRequest,
+    session: Session,
+    path: web::Path<String>,
+    form: Form<LoginLinkForm>,
+    pool: web::Data<SqlitePool>,
+    mailer: web::Data<AsyncSmtpTransport<Tokio1Executor>>,
+    hostname: web::Data<String>,
+) -> Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, form.csrftoken.as_str())?;
+    let token = path.into_inner();
+    let token_hash = hash_token(&token);
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    // Marking the link used in the same statement that checks it means it
+    // can only ever log in once
+    let user_id = sqlx::query_scalar!(
+        r#"UPDATE login_links SET used_at = CURRENT_TIMESTAMP
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > CURRENT_TIMESTAMP
+        RETURNING user_id as "user_id!""#,
+        token_hash
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    let Some(user_id) = user_id else {
+        let csrf_token = CsrfToken::get_or_create(&session)?;
+        let body = LoginLinkPage {
+            title: "Login . Silly Goals".into(),
+            token,
+            valid: false,
+            csrf_token,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?;
+        return Ok(HttpResponse::Ok().body(body));
+    };
+
+    let user = queries::get_user_by_id(&mut conn, user_id).await?;
+
+    login_sessions::login(&mut conn, &req, &session, user.userid, LoginMethod::Link).await?;
+
+    let device = Device::from_request(&req);
+    if remember_device(&mut conn, user.id, &device).await? {
+        notify_new_device(mailer, &hostname, &user, &device, LoginMethod::Link)?;
+    }
+
+    LoginEmail::remove(&session);
+    LoginCode::remove(&session);
+
+    let mut response = HttpResponse::SeeOther();
+    if let Some(cookie) = device.new_cookie {
+        response.cookie(cookie);
+    }
+    Ok(response.insert_header(("Location", "/dashboard")).finish())
+}
+
 #[derive(Deserialize)]
 struct LoginCodeForm {
     pub code: String,
@@ -399,6 +675,8 @@ async fn finish_login(
     session: Session,
     form: Form<LoginCodeForm>,
     pool: web::Data<SqlitePool>,
+    mailer: web::Data<AsyncSmtpTransport<Tokio1Executor>>,
+    hostname: web::Data<String>,
 ) -> Result<HttpResponse> {
     CsrfToken::verify_from_session(&session, form.csrftoken.as_str())?;
 
@@ -430,7 +708,29 @@ async fn finish_login(
         }
     };
 
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
     if !correct_login_code.verify(&form.code) {
+        let user_id =
+            sqlx::query_scalar!("SELECT id FROM users WHERE email = $1;", *user_login_email)
+                .fetch_optional(&mut conn)
+                .await
+                .map_err(ErrorInternalServerError)?;
+        if let Some(user_id) = user_id {
+            record_security_event(
+                &mut conn,
+                &req,
+                user_id,
+                SecurityEventKind::CodeFailed,
+                Some("Login"),
+            )
+            .await?;
+        }
+
         let csrf_token = CsrfToken::get_or_create(&session)?;
         let body = LoginFinish {
             csrf_token,
@@ -445,33 +745,45 @@ async fn finish_login(
         return Ok(HttpResponse::Ok().body(body));
     }
 
-    let mut conn = pool
-        .get_ref()
-        .acquire()
-        .await
-        .map_err(|err| ErrorInternalServerError(err))?;
-
     let user = queries::get_user_by_email(&mut conn, &user_login_email).await?;
 
-    Identity::login(&req.extensions(), user.userid.to_string()).map_err(|err| {
-        error!("Error Logging in new user: {}", err);
-        ErrorInternalServerError(err)
-    })?;
+    login_sessions::login(&mut conn, &req, &session, user.userid, LoginMethod::Code).await?;
+
+    let device = Device::from_request(&req);
+    if remember_device(&mut conn, user.id, &device).await? {
+        notify_new_device(mailer, &hostname, &user, &device, LoginMethod::Code)?;
+    }
 
     LoginEmail::remove(&session);
     LoginCode::remove(&session);
 
-    Ok(HttpResponse::SeeOther()
-        .insert_header(("Location", "/dashboard"))
-        .finish())
+    let mut response = HttpResponse::SeeOther();
+    if let Some(cookie) = device.new_cookie {
+        response.cookie(cookie);
+    }
+    Ok(response.insert_header(("Location", "/dashboard")).finish())
 }
 
 #[get("logout")]
-async fn logout(identity: Identity) -> HttpResponse {
+async fn logout(identity: Identity, pool: web::Data<SqlitePool>) -> Result<HttpResponse> {
+    if let Ok(login_identity) = LoginIdentity::from_identity(&identity) {
+        let mut conn = pool
+            .get_ref()
+            .acquire()
+            .await
+            .map_err(ErrorInternalServerError)?;
+        sqlx::query!(
+            "DELETE FROM login_sessions WHERE session_id = $1;",
+            login_identity.session_id
+        )
+        .execute(&mut conn)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    }
     identity.logout();
-    HttpResponse::SeeOther()
+    Ok(HttpResponse::SeeOther()
         .insert_header(("Location", "/"))
-        .finish()
+        .finish())
 }
 
 /// Display user profie information
@@ -510,11 +822,16 @@ async fn profile(
     Ok(HttpResponse::Ok().body(body))
 }
 
-/// Delete the user's profile
+/// Schedule the user's account for deletion. It's locked straight away, but
+/// kept for a grace period in case they change their mind.
 #[post("/profile/delete")]
 async fn delete_profile(
+    req: HttpRequest,
     identity: Identity,
     pool: web::Data<SqlitePool>,
+    mailer: web::Data<AsyncSmtpTransport<Tokio1Executor>>,
+    hostname: web::Data<String>,
+    grace: web::Data<DeletionGracePeriod>,
 ) -> actix_web::Result<HttpResponse> {
     let mut conn = pool
         .get_ref()
@@ -523,17 +840,181 @@ async fn delete_profile(
         .map_err(ErrorInternalServerError)?;
 
     let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+    require_recent_auth(&mut conn, &req, &identity).await?;
+
+    // Everyone's groups can use the global tones, so they can't go with the account
+    let owns_global_tones = sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM tones WHERE user_id = $1 AND global = 1);",
+        user.id
+    )
+    .fetch_one(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+        != 0;
+    if owns_global_tones {
+        return Err(ErrorConflict(
+            "This account owns the tones everyone can use, so it can't be deleted",
+        ));
+    }
+
+    sqlx::query!(
+        "UPDATE users SET deletion_scheduled_for = datetime('now', '+' || $1 || ' days')
+        WHERE id = $2;",
+        grace.0,
+        user.id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+    record_security_event(
+        &mut conn,
+        &req,
+        user.id,
+        SecurityEventKind::DeletionRequested,
+        None,
+    )
+    .await?;
 
-    sqlx::query!("DELETE FROM users WHERE id = $1", user.id)
+    let restore_url = new_restore_link(&mut conn, &hostname, user.id).await?;
+
+    // Logging out every device is what locks the account
+    sqlx::query!("DELETE FROM login_sessions WHERE user_id = $1;", user.id)
         .execute(&mut conn)
         .await
         .map_err(ErrorInternalServerError)?;
 
+    let deletion_date = Utc::now() + chrono::Duration::days(grace.0);
+    let message = build_email_for_user(
+        &user.email,
+        "Your Silly Goals account will be deleted",
+        &format!(
+            "Your Silly Goals account is locked, and it and all your goals and \
+            groups will be deleted for good on {}.\n\n\
+            Changed your mind? Open this link before then to keep your account: {}",
+            deletion_date.format("%B %-d, %Y"),
+            restore_url
+        ),
+    )?;
+
+    tokio::spawn(async move {
+        match mailer.send(message).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!("Could not sent message: {}", e);
+            }
+        }
+    });
+
     identity.logout();
 
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Confirm before restoring an account. Like login links, email scanners open
+/// these, so only the POST uses the link.
+#[get("/account/restore/{token}")]
+async fn restore_account(
+    session: Session,
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let token = path.into_inner();
+    let token_hash = hash_token(&token);
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let valid = sqlx::query_scalar!(
+        "SELECT id FROM users
+        WHERE restore_token_hash = $1 AND deletion_scheduled_for > CURRENT_TIMESTAMP",
+        token_hash
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .is_some();
+
+    let csrf_token = CsrfToken::get_or_create(&session)?;
+    let body = RestoreAccountPage {
+        title: "Restore Account . Silly Goals".into(),
+        token,
+        valid,
+        restored: false,
+        csrf_token,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(("X-Robots-Tag", "noindex, nofollow"))
+        .insert_header(("Referrer-Policy", "no-referrer"))
+        .body(body))
+}
+
+#[derive(Deserialize)]
+struct RestoreAccountForm {
+    pub csrftoken: String,
+}
+
+/// Cancel an account's deletion, so it can be logged in to again
+#[post("/account/restore/{token}")]
+async fn finish_restore_account(
+    req: HttpRequest,
+    session: Session,
+    path: web::Path<String>,
+    form: Form<RestoreAccountForm>,
+    pool: web::Data<SqlitePool>,
+) -> Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, form.csrftoken.as_str())?;
+    let token = path.into_inner();
+    let token_hash = hash_token(&token);
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let restored_user_id = sqlx::query_scalar!(
+        r#"UPDATE users SET deletion_scheduled_for = NULL, restore_token_hash = NULL
+        WHERE restore_token_hash = $1 AND deletion_scheduled_for > CURRENT_TIMESTAMP
+        RETURNING id as "id!";"#,
+        token_hash
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    if let Some(user_id) = restored_user_id {
+        record_security_event(
+            &mut conn,
+            &req,
+            user_id,
+            SecurityEventKind::DeletionCancelled,
+            None,
+        )
+        .await?;
+    }
+    let restored = restored_user_id.is_some();
+
+    let csrf_token = CsrfToken::get_or_create(&session)?;
+    let body = RestoreAccountPage {
+        title: "Restore Account . Silly Goals".into(),
+        token,
+        valid: restored,
+        restored,
+        csrf_token,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(("X-Robots-Tag", "noindex, nofollow"))
+        .insert_header(("Referrer-Policy", "no-referrer"))
+        .body(body))
+}
+
 /// Edit user's name
 #[get("/profile/edit/name")]
 async fn profile_edit_name(
@@ -596,8 +1077,7 @@ async fn post_profile_edit_name(
         .await
         .map_err(ErrorInternalServerError)?;
 
-    let userid = identity.id().map_err(ErrorInternalServerError)?;
-    let user_uuid = Uuid::parse_str(&userid).map_err(ErrorInternalServerError)?;
+    let user_uuid = LoginIdentity::from_identity(&identity)?.userid;
 
     sqlx::query!(
         "UPDATE users SET name = $1 WHERE userid = $2;",
@@ -642,6 +1122,7 @@ async fn post_profile_edit_name(
 /// Edit user's email
 #[get("/profile/edit/email")]
 async fn profile_edit_email(
+    req: HttpRequest,
     identity: Identity,
     session: Session,
     pool: web::Data<SqlitePool>,
@@ -655,6 +1136,8 @@ async fn profile_edit_email(
         .await
         .map_err(ErrorInternalServerError)?;
     let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+    // Asked before the form, so nothing typed into it is lost
+    require_recent_auth(&mut conn, &req, &identity).await?;
 
     let csrf_token = CsrfToken::get_or_create(&session)?;
 
@@ -694,16 +1177,18 @@ struct UserEmailForm {
 
 #[post("/profile/edit/email")]
 async fn post_profile_edit_email(
+    req: HttpRequest,
     identity: Identity,
     session: Session,
     pool: web::Data<SqlitePool>,
     form: web::Form<UserEmailForm>,
     mailer: web::Data<AsyncSmtpTransport<Tokio1Executor>>,
-    is_hx: IsHtmx,
+    limits: web::Data<EmailLimits>,
 ) -> actix_web::Result<HttpResponse> {
     CsrfToken::verify_from_session(&session, &form.csrftoken)?;
     LoginCode::remove(&session);
     ChangeEmail::remove(&session);
+    let is_hx = req.headers().contains_key("HX-Request");
 
     let mut conn = pool
         .get_ref()
@@ -712,6 +1197,7 @@ async fn post_profile_edit_email(
         .map_err(ErrorInternalServerError)?;
 
     let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+    require_recent_auth(&mut conn, &req, &identity).await?;
     let csrf_token = CsrfToken::get_or_create(&session)?;
 
     let email_exists = sqlx::query_scalar!(
@@ -722,12 +1208,26 @@ async fn post_profile_edit_email(
     .await
     .map_err(ErrorInternalServerError)?;
 
-    if email_exists != 0 {
-        let body = if *is_hx {
+    let error = if email_exists != 0 {
+        Some("Email is not available".to_owned())
+    } else {
+        check_email_limits(
+            &mut conn,
+            &req,
+            &limits,
+            EmailKind::ChangeEmail,
+            &form.email,
+        )
+        .await?
+        .map(wait_message)
+    };
+
+    if let Some(error) = error {
+        let body = if is_hx {
             ProfileEditEmailPartial {
                 user,
                 csrf_token,
-                error: Some("Email is not available".into()),
+                error: Some(error),
             }
             .render()
             .map_err(ErrorInternalServerError)?
@@ -737,7 +1237,7 @@ async fn post_profile_edit_email(
                 title: "Silly Goals".into(),
                 user,
                 csrf_token,
-                error: Some("Email is not available".into()),
+                error: Some(error),
                 groups,
             }
             .render()
@@ -767,7 +1267,16 @@ async fn post_profile_edit_email(
         }
     });
 
-    if *is_hx {
+    record_security_event(
+        &mut conn,
+        &req,
+        user.id,
+        SecurityEventKind::EmailChangeRequested,
+        Some(&form.email),
+    )
+    .await?;
+
+    if is_hx {
         let body = ProfileConfirmEmailPartial {
             csrf_token,
             error: None,
@@ -833,13 +1342,16 @@ async fn confirm_email_invalid_session_response(
 
 #[post("/profile/edit/email/confirm")]
 async fn post_profile_confirm_email(
+    req: HttpRequest,
     identity: Identity,
     session: Session,
     pool: web::Data<SqlitePool>,
     form: web::Form<ConfirmEmailForm>,
-    is_hx: IsHtmx,
+    mailer: web::Data<AsyncSmtpTransport<Tokio1Executor>>,
+    hostname: web::Data<String>,
 ) -> actix_web::Result<HttpResponse> {
     CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let is_hx = IsHtmx(req.headers().contains_key("HX-Request"));
 
     let mut conn = pool
         .get_ref()
@@ -864,6 +1376,15 @@ async fn post_profile_confirm_email(
 
     // If the login code is wrong, send back the code form with an error.
     if !correct_login_code.verify(&form.code) {
+        let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+        record_security_event(
+            &mut conn,
+            &req,
+            user.id,
+            SecurityEventKind::CodeFailed,
+            Some("Email change"),
+        )
+        .await?;
         let csrf_token = CsrfToken::get_or_create(&session)?;
         let body = if *is_hx {
             ProfileConfirmEmailPartial {
@@ -873,7 +1394,6 @@ async fn post_profile_confirm_email(
             .render()
             .map_err(ErrorInternalServerError)?;
         } else {
-            let user = queries::get_user_from_identity(&mut conn, &identity).await?;
             let groups = queries::get_group_links(&mut conn, user.id).await?;
             ProfileConfirmEmailPage {
                 title: "Silly Goals".into(),
@@ -899,8 +1419,11 @@ async fn post_profile_confirm_email(
 
     let change_email = change_email.to_string();
 
-    let userid = identity.id().map_err(ErrorInternalServerError)?;
-    let user_uuid = Uuid::parse_str(&userid).map_err(ErrorInternalServerError)?;
+    let old_email = queries::get_user_from_identity(&mut conn, &identity)
+        .await?
+        .email;
+    let login_identity = LoginIdentity::from_identity(&identity)?;
+    let user_uuid = login_identity.userid;
 
     sqlx::query!(
         "UPDATE users SET email = $1 WHERE userid = $2;",
@@ -912,6 +1435,67 @@ async fn post_profile_confirm_email(
     .map_err(ErrorInternalServerError)?;
 
     let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+    record_security_event(
+        &mut conn,
+        &req,
+        user.id,
+        SecurityEventKind::EmailChangeConfirmed,
+        Some(&format!("{old_email} to {change_email}")),
+    )
+    .await?;
+
+    // After a recovery the old inbox is likely the thing that was lost, and
+    // an undo link sent there would hand the account to whoever has it
+    let login_method = sqlx::query_scalar!(
+        r#"SELECT method as "method: LoginMethod" FROM login_sessions WHERE session_id = $1;"#,
+        login_identity.session_id
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+    if login_method != Some(LoginMethod::Recovery) {
+        // Whoever changed the email can log in with the new one, so the old
+        // address is the only way the real owner hears about it
+        let token = new_login_link_token();
+        let token_hash = hash_token(&token);
+        sqlx::query!(
+            "INSERT INTO email_change_reverts(user_id, old_email, new_email, token_hash, expires_at)
+            VALUES ($1, $2, $3, $4, datetime('now', '+' || $5 || ' days'));",
+            user.id,
+            old_email,
+            change_email,
+            token_hash,
+            EMAIL_CHANGE_REVERT_DAYS
+        )
+        .execute(&mut conn)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+        let message = build_email_for_user(
+            &old_email,
+            "Your Silly Goals email was changed",
+            &format!(
+                "The email for your Silly Goals account was just changed from this \
+                address to {}.\n\n\
+                If this was you, you don't need to do anything. If it wasn't, open \
+                this link to change it back and log out every device: https://{}/email-change/revert/{}\n\n\
+                The link works for the next {} days.",
+                change_email,
+                hostname.as_str(),
+                token,
+                EMAIL_CHANGE_REVERT_DAYS
+            ),
+        )?;
+
+        tokio::spawn(async move {
+            match mailer.send(message).await {
+                Ok(_) => (),
+                Err(e) => {
+                    error!("Could not sent message: {}", e);
+                }
+            }
+        });
+    }
 
     if *is_hx {
         let body = ProfilePartial { user }
@@ -941,3 +1525,213 @@ async fn post_profile_confirm_email(
 
     Ok(HttpResponse::Ok().body(body))
 }
+
+/// Confirm before undoing an email change. Email scanners open links to check
+/// them, so only the POST uses the link up.
+#[get("/email-change/revert/{token}")]
+async fn email_change_revert(
+    session: Session,
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let token = path.into_inner();
+    let token_hash = hash_token(&token);
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let old_email = sqlx::query_scalar!(
+        "SELECT old_email FROM email_change_reverts
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > CURRENT_TIMESTAMP",
+        token_hash
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    let csrf_token = CsrfToken::get_or_create(&session)?;
+    let body = EmailChangeRevertPage {
+        title: "Undo Email Change . Silly Goals".into(),
+        token,
+        valid: old_email.is_some(),
+        old_email,
+        reverted: false,
+        removed_passkeys: 0,
+        removed_recovery_codes: false,
+        error: None,
+        csrf_token,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(("X-Robots-Tag", "noindex, nofollow"))
+        .insert_header(("Referrer-Policy", "no-referrer"))
+        .body(body))
+}
+
+#[derive(Deserialize)]
+struct EmailChangeRevertForm {
+    pub csrftoken: String,
+}
+
+/// Put the old email back and log the account out everywhere, so whoever
+/// changed it has to log in again and can't
+#[post("/email-change/revert/{token}")]
+async fn finish_email_change_revert(
+    req: HttpRequest,
+    identity: Option<Identity>,
+    session: Session,
+    path: web::Path<String>,
+    form: Form<EmailChangeRevertForm>,
+    pool: web::Data<SqlitePool>,
+) -> Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, form.csrftoken.as_str())?;
+    let token = path.into_inner();
+    let token_hash = hash_token(&token);
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    // Marking the link used in the same statement that checks it means it
+    // can only ever be used once
+    let revert = sqlx::query!(
+        r#"UPDATE email_change_reverts SET used_at = CURRENT_TIMESTAMP
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > CURRENT_TIMESTAMP
+        RETURNING user_id as "user_id!", old_email as "old_email!", created_at as "created_at!""#,
+        token_hash
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    let Some(revert) = revert else {
+        let csrf_token = CsrfToken::get_or_create(&session)?;
+        let body = EmailChangeRevertPage {
+            title: "Undo Email Change . Silly Goals".into(),
+            token,
+            valid: false,
+            old_email: None,
+            reverted: false,
+            removed_passkeys: 0,
+            removed_recovery_codes: false,
+            error: None,
+            csrf_token,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?;
+        return Ok(HttpResponse::Ok().body(body));
+    };
+
+    let email_taken = sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM users WHERE email = $1 AND id != $2);",
+        revert.old_email,
+        revert.user_id
+    )
+    .fetch_one(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+        != 0;
+
+    let error = if email_taken {
+        Some(format!(
+            "{} belongs to another account now, so it couldn't be put back. \
+            Every device has still been logged out.",
+            revert.old_email
+        ))
+    } else {
+        sqlx::query!(
+            "UPDATE users SET email = $1 WHERE id = $2;",
+            revert.old_email,
+            revert.user_id
+        )
+        .execute(&mut conn)
+        .await
+        .map_err(ErrorInternalServerError)?;
+        record_security_event(
+            &mut conn,
+            &req,
+            revert.user_id,
+            SecurityEventKind::EmailChangeReverted,
+            Some(&revert.old_email),
+        )
+        .await?;
+        None
+    };
+
+    // Anything whoever made the change could still log in with
+    sqlx::query!(
+        "DELETE FROM login_sessions WHERE user_id = $1;",
+        revert.user_id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+    sqlx::query!(
+        "UPDATE login_links SET used_at = CURRENT_TIMESTAMP
+        WHERE user_id = $1 AND used_at IS NULL;",
+        revert.user_id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    // Whoever made the change could have added their own way back in
+    let removed_passkeys = sqlx::query!(
+        "DELETE FROM webauthn_credentials WHERE user_id = $1 AND created_at >= $2;",
+        revert.user_id,
+        revert.created_at
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .rows_affected();
+    let removed_recovery_codes = sqlx::query!(
+        "DELETE FROM recovery_codes WHERE user_id = $1 AND created_at >= $2;",
+        revert.user_id,
+        revert.created_at
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .rows_affected()
+        > 0;
+    if removed_passkeys > 0 {
+        record_security_event(
+            &mut conn,
+            &req,
+            revert.user_id,
+            SecurityEventKind::PasskeyRemoved,
+            Some(&format!("{removed_passkeys} added after the email change")),
+        )
+        .await?;
+    }
+
+    if let Some(identity) = identity {
+        identity.logout();
+    }
+
+    let csrf_token = CsrfToken::get_or_create(&session)?;
+    let body = EmailChangeRevertPage {
+        title: "Undo Email Change . Silly Goals".into(),
+        token,
+        valid: true,
+        old_email: Some(revert.old_email),
+        reverted: true,
+        removed_passkeys,
+        removed_recovery_codes,
+        error,
+        csrf_token,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(("X-Robots-Tag", "noindex, nofollow"))
+        .insert_header(("Referrer-Policy", "no-referrer"))
+        .body(body))
+}
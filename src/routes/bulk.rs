@@ -0,0 +1,431 @@
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{
+    error::{ErrorBadRequest, ErrorInternalServerError},
+    get, post, web, HttpResponse,
+};
+use askama::Template;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::{Connection, SqlitePool};
+
+use crate::{
+    csrf_token::CsrfToken,
+    html_form::HtmlForm,
+    htmx::{
+        hx_trigger_notification, hx_trigger_notification_with_action, NotificationAction,
+        NotificationVariant,
+    },
+    members::Permission,
+    queries, remap_stage,
+    templates::*,
+    DeadlineType,
+};
+
+fn goal_count(n: u64) -> String {
+    if n == 1 {
+        "1 goal".into()
+    } else {
+        format!("{} goals", n)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BulkActionsQuery {
+    group_id: Option<i64>,
+    #[serde(default)]
+    archived: bool,
+}
+
+/// The toolbar shown while goals are being selected
+#[get("/goals/bulk")]
+async fn bulk_actions(
+    identity: Identity,
+    session: Session,
+    query: web::Query<BulkActionsQuery>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let group = match query.group_id {
+        Some(group_id) => Some(
+            queries::get_group_with_info(&mut conn, user.id, group_id)
+                .await?
+                .into(),
+        ),
+        None => None,
+    };
+    let mut groups = queries::get_group_links(&mut conn, user.id).await?;
+    groups.retain(|g| g.takes_goals_from(query.group_id.unwrap_or_default()));
+    let csrf_token = CsrfToken::get_or_create(&session)?;
+
+    let body = BulkActionsPartial {
+        group,
+        groups,
+        archived: query.archived,
+        csrf_token,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BulkAction {
+    Stage,
+    Group,
+    SetDeadline,
+    ClearDeadline,
+    Archive,
+    Unarchive,
+    Delete,
+}
+
+#[derive(Debug, Deserialize)]
+struct BulkForm {
+    #[serde(default)]
+    goals: Vec<i64>,
+    action: BulkAction,
+    stage: Option<i64>,
+    group_id: Option<i64>,
+    deadline: Option<NaiveDate>,
+    csrftoken: String,
+}
+
+/// Apply one action to all the selected goals. Goals in groups the user can't
+/// edit are ignored, and nothing is changed if any part of the action fails.
+#[post("/goals/bulk")]
+async fn post_bulk_action(
+    identity: Identity,
+    session: Session,
+    form: HtmlForm<BulkForm>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    if form.goals.is_empty() {
+        return Err(ErrorBadRequest("No goals selected"));
+    }
+
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    // the group has to be checked before the transaction holds the connection
+    let to_group = match (&form.action, form.group_id) {
+        (BulkAction::Group, Some(group_id)) => {
+            let group = queries::get_group_with_info(&mut conn, user.id, group_id).await?;
+            group.role.require(Permission::EditGoals)?;
+            Some(group)
+        }
+        _ => None,
+    };
+
+    let ids = serde_json::to_string(&form.goals).map_err(ErrorInternalServerError)?;
+    let selected = form.goals.len() as u64;
+
+    let mut tx = conn.begin().await.map_err(ErrorInternalServerError)?;
+
+    let mut undo = None;
+    let (title, message) = match form.action {
+        BulkAction::Stage => {
+            let stage = form
+                .stage
+                .filter(|s| *s >= 0)
+                .ok_or_else(|| ErrorBadRequest("Pick a stage"))?;
+            let updated = sqlx::query!(
+                "UPDATE goals SET (stage, moved_by, moved_at) = ($1, $3, CURRENT_TIMESTAMP)
+                WHERE id IN (SELECT value FROM json_each($2))
+                AND deleted_at IS NULL
+                AND group_id IN (
+                    SELECT g.id FROM groups g
+                    INNER JOIN tones t
+                    ON g.tone_id = t.id
+                    INNER JOIN group_members m
+                    ON m.group_id = g.id
+                    WHERE m.user_id = $3
+                    AND m.role != 'viewer'
+                    AND g.deleted_at IS NULL
+                    AND g.archived_at IS NULL
+                    AND $1 < json_array_length(t.stages)
+                );",
+                stage,
+                ids,
+                user.id
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(ErrorInternalServerError)?
+            .rows_affected();
+            let mut message = format!("Moved {} to a new stage", goal_count(updated));
+            if updated < selected {
+                message.push_str(&format!(
+                    ", {} skipped because their group doesn't have that stage",
+                    goal_count(selected - updated)
+                ));
+            }
+            ("Stage Updated", message)
+        }
+        BulkAction::Group => {
+            let Some(to_group) = to_group else {
+                return Err(ErrorBadRequest("Pick a group"));
+            };
+            let goals = sqlx::query!(
+                r#"SELECT
+                go.id,
+                go.group_id,
+                go.stage,
+                go.deadline,
+                json_array_length(t.stages) as "stage_count!: i64"
+                FROM goals go
+                INNER JOIN groups g
+                ON go.group_id = g.id
+                INNER JOIN tones t
+                ON g.tone_id = t.id
+                INNER JOIN group_members m
+                ON m.group_id = g.id
+                WHERE m.user_id = $1
+                AND m.role != 'viewer'
+                AND g.deleted_at IS NULL
+                AND g.archived_at IS NULL
+                AND go.deleted_at IS NULL
+                AND go.id IN (SELECT value FROM json_each($2));"#,
+                user.id,
+                ids
+            )
+            .fetch_all(&mut tx)
+            .await
+            .map_err(ErrorInternalServerError)?;
+
+            let mut position = sqlx::query_scalar!(
+                r#"SELECT COALESCE(MAX(position), 0) + 1 as "position!: i64" FROM goals
+                WHERE group_id = $1;"#,
+                to_group.id
+            )
+            .fetch_one(&mut tx)
+            .await
+            .map_err(ErrorInternalServerError)?;
+
+            let mut moved = 0;
+            let mut cleared = 0;
+            for goal in goals.into_iter().filter(|g| g.group_id != to_group.id) {
+                let stage = remap_stage(
+                    goal.stage,
+                    goal.stage_count as usize,
+                    to_group.tone_stages.len(),
+                );
+                let deadline = match to_group.deadline {
+                    DeadlineType::Off => None,
+                    _ => goal.deadline.clone(),
+                };
+                if goal.deadline.is_some() && deadline.is_none() {
+                    cleared += 1;
+                }
+                sqlx::query!(
+                    "UPDATE goals SET (group_id, stage, deadline, position, moved_by, moved_at) =
+                    ($1, $2, $3, $4, $5, CURRENT_TIMESTAMP)
+                    WHERE id = $6;",
+                    to_group.id,
+                    stage,
+                    deadline,
+                    position,
+                    user.id,
+                    goal.id
+                )
+                .execute(&mut tx)
+                .await
+                .map_err(ErrorInternalServerError)?;
+                position += 1;
+                moved += 1;
+            }
+            let mut message = format!("Moved {} to {}", goal_count(moved), to_group.title);
+            if cleared > 0 {
+                message.push_str(&format!(
+                    ", {} lost their deadline because {} doesn't use deadlines",
+                    goal_count(cleared),
+                    to_group.title
+                ));
+            }
+            ("Goals Moved", message)
+        }
+        BulkAction::SetDeadline => {
+            let deadline = form
+                .deadline
+                .ok_or_else(|| ErrorBadRequest("Pick a deadline"))?;
+            let updated = sqlx::query!(
+                "UPDATE goals SET deadline = $1
+                WHERE id IN (SELECT value FROM json_each($2))
+                AND deleted_at IS NULL
+                AND group_id IN (
+                    SELECT g.id FROM groups g
+                    INNER JOIN tones t
+                    ON g.tone_id = t.id
+                    INNER JOIN group_members m
+                    ON m.group_id = g.id
+                    WHERE m.user_id = $3
+                    AND m.role != 'viewer'
+                    AND g.deleted_at IS NULL
+                    AND g.archived_at IS NULL
+                    AND t.deadline != 'off'
+                );",
+                deadline,
+                ids,
+                user.id
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(ErrorInternalServerError)?
+            .rows_affected();
+            let mut message = format!(
+                "{} now due {}",
+                goal_count(updated),
+                deadline.format("%B %-d, %Y")
+            );
+            if updated < selected {
+                message.push_str(&format!(
+                    ", {} skipped because their group doesn't use deadlines",
+                    goal_count(selected - updated)
+                ));
+            }
+            ("Deadline Set", message)
+        }
+        BulkAction::ClearDeadline => {
+            let updated = sqlx::query!(
+                "UPDATE goals SET deadline = NULL
+                WHERE id IN (SELECT value FROM json_each($1))
+                AND deleted_at IS NULL
+                AND group_id IN (
+                    SELECT g.id FROM groups g
+                    INNER JOIN group_members m
+                    ON m.group_id = g.id
+                    WHERE m.user_id = $2
+                    AND m.role != 'viewer'
+                    AND g.deleted_at IS NULL
+                    AND g.archived_at IS NULL
+                );",
+                ids,
+                user.id
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(ErrorInternalServerError)?
+            .rows_affected();
+            (
+                "Deadline Cleared",
+                format!("Removed the deadline from {}", goal_count(updated)),
+            )
+        }
+        BulkAction::Archive => {
+            let updated = sqlx::query!(
+                "UPDATE goals SET archived_at = CURRENT_TIMESTAMP
+                WHERE id IN (SELECT value FROM json_each($1))
+                AND deleted_at IS NULL
+                AND group_id IN (
+                    SELECT g.id FROM groups g
+                    INNER JOIN group_members m
+                    ON m.group_id = g.id
+                    WHERE m.user_id = $2
+                    AND m.role != 'viewer'
+                    AND g.deleted_at IS NULL
+                    AND g.archived_at IS NULL
+                )
+                AND archived_at IS NULL;",
+                ids,
+                user.id
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(ErrorInternalServerError)?
+            .rows_affected();
+            (
+                "Goals Archived",
+                format!("Archived {}", goal_count(updated)),
+            )
+        }
+        BulkAction::Unarchive => {
+            let updated = sqlx::query!(
+                "UPDATE goals SET archived_at = NULL
+                WHERE id IN (SELECT value FROM json_each($1))
+                AND deleted_at IS NULL
+                AND group_id IN (
+                    SELECT g.id FROM groups g
+                    INNER JOIN group_members m
+                    ON m.group_id = g.id
+                    WHERE m.user_id = $2
+                    AND m.role != 'viewer'
+                    AND g.deleted_at IS NULL
+                    AND g.archived_at IS NULL
+                )
+                AND archived_at IS NOT NULL;",
+                ids,
+                user.id
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(ErrorInternalServerError)?
+            .rows_affected();
+            (
+                "Goals Restored",
+                format!("Moved {} out of the archive", goal_count(updated)),
+            )
+        }
+        BulkAction::Delete => {
+            let deleted = sqlx::query!(
+                "UPDATE goals SET deleted_at = CURRENT_TIMESTAMP
+                WHERE id IN (SELECT value FROM json_each($1))
+                AND deleted_at IS NULL
+                AND group_id IN (
+                    SELECT g.id FROM groups g
+                    INNER JOIN group_members m
+                    ON m.group_id = g.id
+                    WHERE m.user_id = $2
+                    AND m.role != 'viewer'
+                    AND g.deleted_at IS NULL
+                    AND g.archived_at IS NULL
+                );",
+                ids,
+                user.id
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(ErrorInternalServerError)?
+            .rows_affected();
+            undo = Some(NotificationAction {
+                label: "Undo".into(),
+                url: "/trash/goals/restore".into(),
+                values: json!({ "goals": form.goals }),
+            });
+            (
+                "Goals Deleted",
+                format!("Moved {} to the trash", goal_count(deleted)),
+            )
+        }
+    };
+
+    tx.commit().await.map_err(ErrorInternalServerError)?;
+
+    let notification = match undo {
+        Some(action) => hx_trigger_notification_with_action(
+            title.into(),
+            message,
+            NotificationVariant::Success,
+            action,
+        ),
+        None => hx_trigger_notification(title.into(), message, NotificationVariant::Success, true),
+    };
+
+    Ok(HttpResponse::Ok()
+        .append_header(notification)
+        .insert_header(("HX-Trigger", "goalsChanged"))
+        .finish())
+}
@@ -0,0 +1,228 @@
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{
+    error::{ErrorInternalServerError, ErrorNotFound},
+    get, post, web, HttpRequest, HttpResponse,
+};
+use askama::Template;
+use base64::{engine::general_purpose, Engine};
+use chrono::Utc;
+use rand::{thread_rng, Rng};
+use serde::Deserialize;
+use sqlx::{pool::PoolConnection, Sqlite, SqlitePool};
+
+use crate::{
+    csrf_token::CsrfToken,
+    htmx::{hx_trigger_notification, NotificationVariant},
+    ics, queries,
+    reauth::require_recent_auth,
+    security_events::{record_security_event, SecurityEventKind},
+    templates::*,
+};
+
+fn new_calendar_token() -> String {
+    let mut token_bytes = [0u8; 32];
+    thread_rng().fill(&mut token_bytes);
+    general_purpose::URL_SAFE_NO_PAD.encode(token_bytes)
+}
+
+async fn get_calendar_token(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+) -> actix_web::Result<Option<String>> {
+    sqlx::query_scalar!("SELECT calendar_token FROM users WHERE id = $1", user_id)
+        .fetch_one(conn)
+        .await
+        .map_err(ErrorInternalServerError)
+}
+
+fn render_feed_settings(
+    hostname: &str,
+    token: Option<String>,
+    csrf_token: CsrfToken,
+) -> actix_web::Result<String> {
+    CalendarFeedPartial {
+        feed_url: token.map(|t| format!("https://{}/calendar/feed/{}.ics", hostname, t)),
+        csrf_token,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)
+}
+
+/// Serve the user's goal deadlines as an iCalendar feed. The token in the url
+/// is the only authentication so calendar apps can subscribe.
+#[get("/calendar/feed/{token}.ics")]
+async fn calendar_feed(
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+    hostname: web::Data<String>,
+) -> actix_web::Result<HttpResponse> {
+    let token = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let user_id = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE calendar_token = $1 AND deletion_scheduled_for IS NULL",
+        token
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .ok_or_else(|| ErrorNotFound("Unknown calendar feed"))?;
+
+    let goals = queries::get_all_goals_with_deadlines(&mut conn, user_id).await?;
+
+    let body = ics::goals_calendar(hostname.as_str(), &goals, Utc::now().naive_utc());
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .insert_header(("X-Robots-Tag", "noindex"))
+        .insert_header(("Cache-Control", "private, max-age=300"))
+        .body(body))
+}
+
+/// Show the calendar feed url on the profile page
+#[get("/profile/calendar-feed")]
+async fn profile_calendar_feed(
+    identity: Identity,
+    session: Session,
+    pool: web::Data<SqlitePool>,
+    hostname: web::Data<String>,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let token = get_calendar_token(&mut conn, user.id).await?;
+    let csrf_token = CsrfToken::get_or_create(&session)?;
+
+    let body = render_feed_settings(hostname.as_str(), token, csrf_token)?;
+    Ok(HttpResponse::Ok().body(body))
+}
+
+#[derive(Deserialize)]
+struct CalendarFeedForm {
+    csrftoken: String,
+}
+
+/// Create a new calendar feed url, replacing the old one if it exists
+#[post("/profile/calendar-feed")]
+async fn rotate_calendar_feed_token(
+    req: HttpRequest,
+    identity: Identity,
+    session: Session,
+    pool: web::Data<SqlitePool>,
+    hostname: web::Data<String>,
+    form: web::Form<CalendarFeedForm>,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+    require_recent_auth(&mut conn, &req, &identity).await?;
+
+    let had_token = get_calendar_token(&mut conn, user.id).await?.is_some();
+    let token = new_calendar_token();
+    sqlx::query!(
+        "UPDATE users SET calendar_token = $1 WHERE id = $2;",
+        token,
+        user.id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+    let detail = if had_token {
+        "Calendar feed, replacing the old link"
+    } else {
+        "Calendar feed"
+    };
+    record_security_event(
+        &mut conn,
+        &req,
+        user.id,
+        SecurityEventKind::ApiTokenCreated,
+        Some(detail),
+    )
+    .await?;
+
+    let notification = if had_token {
+        hx_trigger_notification(
+            "Calendar Link Changed".into(),
+            "The old link will no longer work, update your calendar app to use the new one".into(),
+            NotificationVariant::Success,
+            false,
+        )
+    } else {
+        hx_trigger_notification(
+            "Calendar Link Created".into(),
+            "Add the link to your calendar app to subscribe".into(),
+            NotificationVariant::Success,
+            true,
+        )
+    };
+
+    let csrf_token = CsrfToken::get_or_create(&session)?;
+    let body = render_feed_settings(hostname.as_str(), Some(token), csrf_token)?;
+    Ok(HttpResponse::Ok().append_header(notification).body(body))
+}
+
+/// Turn off the calendar feed
+#[post("/profile/calendar-feed/disable")]
+async fn disable_calendar_feed(
+    req: HttpRequest,
+    identity: Identity,
+    session: Session,
+    pool: web::Data<SqlitePool>,
+    hostname: web::Data<String>,
+    form: web::Form<CalendarFeedForm>,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let disabled = sqlx::query!(
+        "UPDATE users SET calendar_token = NULL WHERE id = $1 AND calendar_token IS NOT NULL;",
+        user.id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .rows_affected()
+        > 0;
+    if disabled {
+        record_security_event(
+            &mut conn,
+            &req,
+            user.id,
+            SecurityEventKind::ApiTokenRevoked,
+            Some("Calendar feed"),
+        )
+        .await?;
+    }
+
+    let notification = hx_trigger_notification(
+        "Calendar Link Disabled".into(),
+        "Your goals are no longer shared with your calendar app".into(),
+        NotificationVariant::Success,
+        true,
+    );
+
+    let csrf_token = CsrfToken::get_or_create(&session)?;
+    let body = render_feed_settings(hostname.as_str(), None, csrf_token)?;
+    Ok(HttpResponse::Ok().append_header(notification).body(body))
+}
@@ -1,4 +1,4 @@
-use std::unreachable;
+use std::{cmp::Ordering, unreachable};
 
 use actix_identity::Identity;
 use actix_session::Session;
@@ -8,20 +8,28 @@ use actix_web::{
     get, patch, post, web, HttpResponse,
 };
 use askama::Template;
+use chrono::{Duration, Utc};
 use log::error;
 use serde::Deserialize;
-use sqlx::{types::Json, SqlitePool};
+use sqlx::{types::Json, Connection, SqlitePool};
 
 use crate::{
+    calendar::{Calendar, CalendarView},
+    clean_group_icon,
     csrf_token::CsrfToken,
+    html_form::HtmlForm,
     htmx::{hx_trigger_notification, HxHeaderInfo},
     htmx::{IsHtmx, NotificationVariant},
-    queries,
+    markdown,
+    members::Permission,
+    queries, remap_stage,
+    stats::GroupStats,
     templates::*,
-    DeadlineType, Goal, GoalBehavior, Group, Tone,
+    DeadlineType, Goal, GoalBehavior, GoalSort, GroupDisplay, GroupLink, LabelColor, Priority,
+    Tone,
 };
 
-fn group_goals_by_stage(goals: &[Goal]) -> Vec<Vec<Goal>> {
+pub(crate) fn group_goals_by_stage(goals: &[Goal], sort: GoalSort) -> Vec<Vec<Goal>> {
     let mut goals_in_stages = vec![vec![]; 4];
 
     for goal in goals.iter() {
@@ -31,6 +39,9 @@ fn group_goals_by_stage(goals: &[Goal]) -> Vec<Vec<Goal>> {
             error!("Goal has invalid stage, skipping: {:#?}", goal)
         }
     }
+    for stage in goals_in_stages.iter_mut() {
+        sort.sort(stage);
+    }
     goals_in_stages
 }
 
@@ -48,20 +59,177 @@ async fn dashboard(
         .map_err(ErrorInternalServerError)?;
     let user = queries::get_user_from_identity(&mut conn, &identity).await?;
 
-    let groups = sqlx::query_as!(Group, "SELECT * FROM groups WHERE user_id = $1", user.id)
-        .fetch_all(&mut conn)
+    let groups = queries::get_groups(&mut conn, user.id).await?;
+    let invitations = queries::get_pending_invitations(&mut conn, &user.email).await?;
+
+    let body = if *is_hx && !hx_headers.boosted {
+        DashboardPartial {
+            groups,
+            user,
+            invitations,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?
+    } else {
+        DashboardPage {
+            title: "Silly Goals".into(),
+            user,
+            groups,
+            invitations,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?
+    };
+    Ok(HttpResponse::Ok()
+        .insert_header(("HX-Trigger-After-Swap", "updateLocation"))
+        .body(body))
+}
+
+#[derive(Debug, Deserialize)]
+struct TodayQuery {
+    /// The label filter select sends an empty string for all labels
+    label: Option<String>,
+}
+
+/// Show goals from every group that are overdue or due soon. Groups that don't
+/// use deadlines are left out.
+#[get("/today")]
+async fn today(
+    identity: Identity,
+    query: web::Query<TodayQuery>,
+    pool: web::Data<SqlitePool>,
+    is_hx: IsHtmx,
+    hx_headers: HxHeaderInfo,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool
+        .get_ref()
+        .acquire()
         .await
         .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let current_date = Utc::now().date_naive();
+    let today = current_date.format("%Y-%m-%d").to_string();
+    let week_end = (current_date + Duration::days(7))
+        .format("%Y-%m-%d")
+        .to_string();
+
+    let selected_label = query.label.as_deref().and_then(|l| l.parse::<i64>().ok());
+
+    let goals = queries::get_upcoming_goals(&mut conn, user.id, &week_end).await?;
+    let labels = queries::get_labels(&mut conn, user.id).await?;
+
+    let mut overdue = vec![];
+    let mut due_today = vec![];
+    let mut due_this_week = vec![];
+    for goal in goals {
+        if selected_label.is_some_and(|l| !goal.has_label(&l)) {
+            continue;
+        }
+        match goal.deadline.cmp(&today) {
+            Ordering::Less => overdue.push(goal),
+            Ordering::Equal => due_today.push(goal),
+            Ordering::Greater => due_this_week.push(goal),
+        }
+    }
 
     let body = if *is_hx && !hx_headers.boosted {
-        DashboardPartial { groups, user }
-            .render()
-            .map_err(ErrorInternalServerError)?
+        TodayPartial {
+            overdue,
+            due_today,
+            due_this_week,
+            labels,
+            selected_label,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?
     } else {
-        DashboardPage {
+        let groups = queries::get_group_links(&mut conn, user.id).await?;
+        TodayPage {
+            title: "Silly Goals".into(),
+            user,
+            groups,
+            overdue,
+            due_today,
+            due_this_week,
+            labels,
+            selected_label,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?
+    };
+    Ok(HttpResponse::Ok()
+        .insert_header(("HX-Trigger-After-Swap", "updateLocation"))
+        .body(body))
+}
+
+#[derive(Debug, Deserialize)]
+struct CalendarQuery {
+    view: Option<CalendarView>,
+    date: Option<chrono::NaiveDate>,
+    /// The group and label filter selects send an empty string for all
+    group: Option<String>,
+    label: Option<String>,
+}
+
+/// Show goals on a month or week calendar by their deadline
+#[get("/calendar")]
+async fn calendar(
+    identity: Identity,
+    query: web::Query<CalendarQuery>,
+    pool: web::Data<SqlitePool>,
+    is_hx: IsHtmx,
+    hx_headers: HxHeaderInfo,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let query = query.into_inner();
+    let current_date = Utc::now().date_naive();
+    let selected_group = query.group.and_then(|g| g.parse::<i64>().ok());
+    let selected_label = query.label.and_then(|l| l.parse::<i64>().ok());
+
+    let mut calendar = Calendar::new(
+        query.view.unwrap_or_default(),
+        query.date.unwrap_or(current_date),
+        current_date,
+        selected_group,
+        selected_label,
+    )
+    .ok_or_else(|| ErrorBadRequest("That date is too far away to show"))?;
+
+    let goals = queries::get_goals_with_deadlines_between(
+        &mut conn,
+        user.id,
+        &calendar.start.format("%Y-%m-%d").to_string(),
+        &calendar.end.format("%Y-%m-%d").to_string(),
+        calendar.group,
+    )
+    .await?;
+    calendar.add_goals(goals);
+
+    let groups = queries::get_group_links(&mut conn, user.id).await?;
+    let labels = queries::get_labels(&mut conn, user.id).await?;
+
+    let body = if *is_hx && !hx_headers.boosted {
+        CalendarPartial {
+            calendar,
+            groups,
+            labels,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?
+    } else {
+        CalendarPage {
             title: "Silly Goals".into(),
             user,
             groups,
+            calendar,
+            labels,
         }
         .render()
         .map_err(ErrorInternalServerError)?
@@ -71,6 +239,44 @@ async fn dashboard(
         .body(body))
 }
 
+#[derive(Debug, Deserialize)]
+struct SearchQuery {
+    q: String,
+}
+
+/// Search goal and group titles and descriptions for the sidebar
+#[get("/search")]
+async fn search(
+    identity: Identity,
+    query: web::Query<SearchQuery>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let (goals, groups) = match queries::fts_query(&query.q) {
+        Some(fts_query) => (
+            queries::search_goals(&mut conn, user.id, &fts_query).await?,
+            queries::search_groups(&mut conn, user.id, &fts_query).await?,
+        ),
+        None => (vec![], vec![]),
+    };
+
+    let body = SearchResultsPartial {
+        query: query.into_inner().q,
+        goals,
+        groups,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
 #[get("/finish-tutorial")]
 async fn finish_tutorial(
     identity: Identity,
@@ -96,13 +302,15 @@ async fn finish_tutorial(
     user.is_new_user = false;
 
     if *is_hx && !hx_headers.boosted {
-        let groups = sqlx::query_as!(Group, "SELECT * FROM groups WHERE user_id = $1", user.id)
-            .fetch_all(&mut conn)
-            .await
-            .map_err(ErrorInternalServerError)?;
-        let body = DashboardPartial { groups, user }
-            .render()
-            .map_err(ErrorInternalServerError)?;
+        let groups = queries::get_groups(&mut conn, user.id).await?;
+        let invitations = queries::get_pending_invitations(&mut conn, &user.email).await?;
+        let body = DashboardPartial {
+            groups,
+            user,
+            invitations,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?;
         return Ok(HttpResponse::Ok()
             .insert_header(("HX-Trigger-After-Swap", "updateLocation"))
             .body(body));
@@ -151,10 +359,8 @@ async fn new_group(
             .body(body));
     }
 
-    let groups = sqlx::query_as!(Group, "SELECT * FROM groups WHERE user_id = $1", user.id)
-        .fetch_all(&mut conn)
-        .await
-        .map_err(ErrorInternalServerError)?;
+    let groups = queries::get_groups(&mut conn, user.id).await?;
+    let invitations = queries::get_pending_invitations(&mut conn, &user.email).await?;
 
     let body = NewGroupPage {
         title: "Silly Goals".into(),
@@ -162,6 +368,7 @@ async fn new_group(
         tones,
         csrf_token,
         groups,
+        invitations,
     }
     .render()
     .map_err(ErrorInternalServerError)?;
@@ -174,17 +381,20 @@ struct GroupForm {
     title: String,
     description: Option<String>,
     tone_id: i64,
+    icon: Option<String>,
+    color: Option<LabelColor>,
     csrftoken: String,
 }
 
 #[post("/groups/new")]
 async fn post_new_group(
     identity: Identity,
-    form: web::Form<GroupForm>,
+    form: HtmlForm<GroupForm>,
     session: Session,
     pool: web::Data<SqlitePool>,
 ) -> actix_web::Result<HttpResponse> {
     CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let icon = clean_group_icon(form.icon.as_deref()).map_err(ErrorBadRequest)?;
 
     let mut conn = pool
         .get_ref()
@@ -194,21 +404,39 @@ async fn post_new_group(
 
     let user = queries::get_user_from_identity(&mut conn, &identity).await?;
 
-    let created_group_id = sqlx::query_scalar!(
-        "INSERT INTO groups(title, description, tone_id, user_id)
-        VALUES ($1, $2, $3, $4)
-        RETURNING id;",
+    // New groups go at the end of the list
+    let position = queries::get_next_group_position(&mut conn, user.id).await?;
+
+    // RETURNING on groups is too much for the query macros now that members
+    // hang off it, so the new id comes from the insert result instead
+    let created_group_id = sqlx::query!(
+        "INSERT INTO groups(title, description, tone_id, user_id, icon, color)
+        VALUES ($1, $2, $3, $4, $5, $6);",
         form.title,
         form.description,
         form.tone_id,
-        user.id
+        user.id,
+        icon,
+        form.color
     )
-    .fetch_one(&mut conn)
+    .execute(&mut conn)
     .await
     .map_err(|err| {
         error!("Could not insert record: {}", err);
         ErrorInternalServerError(err)
-    })?;
+    })?
+    .last_insert_rowid();
+
+    sqlx::query!(
+        "INSERT INTO group_members(group_id, user_id, role, position)
+        VALUES ($1, $2, 'owner', $3);",
+        created_group_id,
+        user.id,
+        position
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
 
     let notification = hx_trigger_notification(
         format!("Created {}", form.title),
@@ -241,25 +469,8 @@ async fn dashboard_edit_group(
 
     let user = queries::get_user_from_identity(&mut conn, &identity).await?;
 
-    let group = sqlx::query_as!(
-        Group,
-        r#"SELECT 
-        id,
-        title,
-        description,
-        tone_id,
-        user_id
-        FROM groups
-        WHERE user_id = $1 AND id = $2;"#,
-        user.id,
-        group_id
-    )
-    .fetch_one(&mut conn)
-    .await
-    .map_err(|err| match err {
-        sqlx::Error::RowNotFound => ErrorNotFound(err),
-        e => ErrorInternalServerError(e),
-    })?;
+    let group = queries::get_group(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::ManageGroup)?;
 
     let csrf_token = CsrfToken::get_or_create(&session)?;
 
@@ -290,10 +501,8 @@ async fn dashboard_edit_group(
             .body(body));
     }
 
-    let groups = sqlx::query_as!(Group, "SELECT * FROM groups WHERE user_id = $1", user.id)
-        .fetch_all(&mut conn)
-        .await
-        .map_err(ErrorInternalServerError)?;
+    let groups = queries::get_groups(&mut conn, user.id).await?;
+    let invitations = queries::get_pending_invitations(&mut conn, &user.email).await?;
 
     let body = DashboardEditGroupPage {
         title: "Silly Goals".into(),
@@ -302,6 +511,7 @@ async fn dashboard_edit_group(
         groups,
         csrf_token,
         tones,
+        invitations,
     }
     .render()
     .map_err(ErrorInternalServerError)?;
@@ -314,6 +524,8 @@ struct EditGroupForm {
     title: String,
     description: Option<String>,
     tone_id: i64,
+    icon: Option<String>,
+    color: Option<LabelColor>,
     csrftoken: String,
     return_to: String,
 }
@@ -324,12 +536,13 @@ struct EditGroupForm {
 async fn post_edit_group(
     identity: Identity,
     path: web::Path<i64>,
-    form: web::Form<EditGroupForm>,
+    form: HtmlForm<EditGroupForm>,
     session: Session,
     pool: web::Data<SqlitePool>,
     is_hx: IsHtmx,
 ) -> actix_web::Result<HttpResponse> {
     CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let icon = clean_group_icon(form.icon.as_deref()).map_err(ErrorBadRequest)?;
     let group_id = path.into_inner();
     if form.return_to != "/dashboard" && form.return_to != format!("/groups/{}", group_id) {
         return Err(ErrorBadRequest("Invalid return_to"));
@@ -343,17 +556,21 @@ async fn post_edit_group(
 
     let user = queries::get_user_from_identity(&mut conn, &identity).await?;
 
+    let group = queries::get_group(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::ManageGroup)?;
+
     sqlx::query!(
         "UPDATE groups
         SET 
-        title = $1, description = $2, tone_id = $3
+        title = $1, description = $2, tone_id = $3, icon = $4, color = $5
         WHERE 
-        id = $4 AND user_id = $5;",
+        id = $6 AND deleted_at IS NULL AND archived_at IS NULL;",
         form.title,
         form.description,
         form.tone_id,
-        group_id,
-        user.id,
+        icon,
+        form.color,
+        group.id,
     )
     .execute(&mut conn)
     .await
@@ -361,17 +578,19 @@ async fn post_edit_group(
 
     if *is_hx {
         let body = if form.return_to == "/dashboard" {
-            let groups = sqlx::query_as!(Group, "SELECT * FROM groups WHERE user_id = $1", user.id)
-                .fetch_all(&mut conn)
-                .await
-                .map_err(ErrorInternalServerError)?;
-            DashboardPartial { groups, user }
-                .render()
-                .map_err(ErrorInternalServerError)?
+            let groups = queries::get_groups(&mut conn, user.id).await?;
+            let invitations = queries::get_pending_invitations(&mut conn, &user.email).await?;
+            DashboardPartial {
+                groups,
+                user,
+                invitations,
+            }
+            .render()
+            .map_err(ErrorInternalServerError)?
         } else if form.return_to == format!("/groups/{}", group_id) {
             let group = queries::get_group_with_info(&mut conn, user.id, group_id).await?;
             let goals = queries::get_goals_for_group(&mut conn, group.id).await?;
-            let goals_in_stages = group_goals_by_stage(&goals);
+            let goals_in_stages = group_goals_by_stage(&goals, group.goal_sort);
 
             ShowGroupPartial {
                 group: group.into(),
@@ -421,7 +640,7 @@ async fn get_group(
 
     let goals = queries::get_goals_for_group(&mut conn, group_id).await?;
 
-    let goals_in_stages = group_goals_by_stage(&goals);
+    let goals_in_stages = group_goals_by_stage(&goals, group.goal_sort);
 
     if *is_hx && !hx_header.boosted {
         let body = ShowGroupPartial {
@@ -448,7 +667,254 @@ async fn get_group(
     Ok(HttpResponse::Ok().body(body))
 }
 
-/// Delete a group and all its goals
+#[derive(Debug, Deserialize)]
+struct GoalSortForm {
+    sort: GoalSort,
+}
+
+/// Change and remember how the goals in a group are sorted
+#[patch("/groups/{id}/sort")]
+async fn patch_goal_sort(
+    identity: Identity,
+    path: web::Path<i64>,
+    form: web::Form<GoalSortForm>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let group_id = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    queries::set_goal_sort(&mut conn, user.id, group_id, form.sort).await?;
+
+    let group = queries::get_group_with_info(&mut conn, user.id, group_id).await?;
+    let goals = queries::get_goals_for_group(&mut conn, group_id).await?;
+    let goals_in_stages = group_goals_by_stage(&goals, group.goal_sort);
+
+    let body = ShowGroupPartial {
+        group: group.into(),
+        goals_in_stages,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(("HX-Trigger-After-Swap", "updateLocation"))
+        .body(body))
+}
+
+/// Just the group links from the sidebar, so they can be refreshed when groups
+/// are reordered, pinned or archived
+#[get("/sidebar/groups")]
+async fn sidebar_groups(
+    identity: Identity,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+    let groups = queries::get_group_links(&mut conn, user.id).await?;
+
+    let body = GroupLinksPartial { groups }
+        .render()
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+#[derive(Debug, Deserialize)]
+struct GroupOrderForm {
+    #[serde(default)]
+    groups: Vec<i64>,
+}
+
+/// Save the order groups were dragged into on the dashboard. Pinned groups
+/// still come first.
+#[patch("/groups/order")]
+async fn patch_group_order(
+    identity: Identity,
+    form: HtmlForm<GroupOrderForm>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let mut tx = conn.begin().await.map_err(ErrorInternalServerError)?;
+    for (position, group_id) in (1_i64..).zip(form.groups.iter()) {
+        sqlx::query!(
+            "UPDATE group_members SET position = $1 WHERE group_id = $2 AND user_id = $3;",
+            position,
+            group_id,
+            user.id
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    }
+    tx.commit().await.map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(("HX-Trigger", "groupsChanged"))
+        .finish())
+}
+
+#[derive(Debug, Deserialize)]
+struct PinForm {
+    pinned: bool,
+}
+
+/// Pin a group to the top of the dashboard and sidebar, or unpin it
+#[patch("/groups/{id}/pin")]
+async fn patch_group_pin(
+    identity: Identity,
+    path: web::Path<i64>,
+    form: web::Form<PinForm>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let group_id = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    // Pins belong to the member, so everyone sharing a group can pin it for
+    // themselves
+    let group = queries::get_group(&mut conn, user.id, group_id).await?;
+
+    sqlx::query!(
+        "UPDATE group_members SET pinned = $1 WHERE group_id = $2 AND user_id = $3;",
+        form.pinned,
+        group.id,
+        user.id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(("HX-Trigger", "groupsChanged"))
+        .finish())
+}
+
+/// Show completion stats and the cumulative flow of goals for a group
+#[get("/groups/{id}/stats")]
+async fn group_stats(
+    identity: Identity,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+    is_hx: IsHtmx,
+    hx_header: HxHeaderInfo,
+) -> actix_web::Result<HttpResponse> {
+    let group_id = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let group: GroupDisplay = queries::get_group_with_info(&mut conn, user.id, group_id)
+        .await?
+        .into();
+
+    let goals = queries::get_goals_for_group(&mut conn, group_id).await?;
+
+    let transitions = queries::get_stage_transitions_for_group(&mut conn, group_id).await?;
+
+    let stats = GroupStats::build(
+        &group.tone_stages,
+        &group.deadline,
+        &goals,
+        &transitions,
+        Utc::now().naive_utc(),
+    );
+
+    if *is_hx && !hx_header.boosted {
+        let body = GroupStatsPartial { group, stats }
+            .render()
+            .map_err(ErrorInternalServerError)?;
+        return Ok(HttpResponse::Ok().body(body));
+    }
+
+    let groups = queries::get_group_links(&mut conn, user.id).await?;
+
+    let body = GroupStatsPage {
+        title: "Silly Goals".into(),
+        user,
+        group,
+        stats,
+        groups,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+/// List a group's archived goals so they can be restored
+#[get("/groups/{id}/archived")]
+async fn archived_goals(
+    identity: Identity,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+    is_hx: IsHtmx,
+    hx_header: HxHeaderInfo,
+) -> actix_web::Result<HttpResponse> {
+    let group_id = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let group: GroupDisplay = queries::get_group_with_info(&mut conn, user.id, group_id)
+        .await?
+        .into();
+
+    let goals = queries::get_archived_goals_for_group(&mut conn, group_id).await?;
+
+    if *is_hx && !hx_header.boosted {
+        let body = ArchivedGoalsPartial { group, goals }
+            .render()
+            .map_err(ErrorInternalServerError)?;
+        return Ok(HttpResponse::Ok().body(body));
+    }
+
+    let groups = queries::get_group_links(&mut conn, user.id).await?;
+
+    let body = ArchivedGoalsPage {
+        title: "Silly Goals".into(),
+        user,
+        groups,
+        group,
+        goals,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+/// Move a group and all its goals to the trash
 #[delete("/groups/{id}")]
 async fn delete_group(
     identity: Identity,
@@ -464,10 +930,13 @@ async fn delete_group(
 
     let user = queries::get_user_from_identity(&mut conn, &identity).await?;
 
+    let group = queries::get_group(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::ManageGroup)?;
+
     sqlx::query!(
-        r#"DELETE FROM groups WHERE user_id = $1 AND id = $2;"#,
-        user.id,
-        group_id
+        r#"UPDATE groups SET deleted_at = CURRENT_TIMESTAMP
+        WHERE id = $1 AND deleted_at IS NULL;"#,
+        group.id
     )
     .execute(&mut conn)
     .await
@@ -506,6 +975,8 @@ async fn new_goal(
     let groups = queries::get_group_links(&mut conn, user.id).await?;
 
     let group = queries::get_group_with_info(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::EditGoals)?;
+    let labels = queries::get_labels(&mut conn, user.id).await?;
     let csrf_token = CsrfToken::get_or_create(&session)?;
 
     if *is_hx {
@@ -513,6 +984,7 @@ async fn new_goal(
             group: group.into(),
             csrf_token,
             selected_stage,
+            labels,
         }
         .render()
         .map_err(ErrorInternalServerError)?;
@@ -523,7 +995,7 @@ async fn new_goal(
     }
 
     let goals = queries::get_goals_for_group(&mut conn, group_id).await?;
-    let goals_in_stages = group_goals_by_stage(&goals);
+    let goals_in_stages = group_goals_by_stage(&goals, group.goal_sort);
 
     let body = NewGoalPage {
         title: "Silly Goals".into(),
@@ -533,6 +1005,7 @@ async fn new_goal(
         selected_stage,
         csrf_token,
         groups,
+        labels,
     }
     .render()
     .map_err(ErrorInternalServerError)?;
@@ -546,6 +1019,10 @@ struct NewGoalForm {
     description: Option<String>,
     deadline: Option<chrono::NaiveDate>,
     stage: i16,
+    #[serde(default)]
+    priority: Priority,
+    #[serde(default)]
+    labels: Vec<i64>,
     csrftoken: String,
 }
 
@@ -553,7 +1030,7 @@ struct NewGoalForm {
 async fn post_new_goal(
     identity: Identity,
     path: web::Path<i64>,
-    form: web::Form<NewGoalForm>,
+    form: HtmlForm<NewGoalForm>,
     session: Session,
     pool: web::Data<SqlitePool>,
     is_hx: IsHtmx,
@@ -569,37 +1046,33 @@ async fn post_new_goal(
 
     let user = queries::get_user_from_identity(&mut conn, &identity).await?;
 
-    let group = sqlx::query_as!(
-        Group,
-        r#"SELECT * FROM groups 
-        WHERE user_id = $1 AND id = $2;"#,
-        user.id,
-        group_id
-    )
-    .fetch_one(&mut conn)
-    .await
-    .map_err(|err| match err {
-        sqlx::Error::RowNotFound => ErrorNotFound(err),
-        e => ErrorInternalServerError(e),
-    })?;
+    let group = queries::get_group(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::EditGoals)?;
+    let position = queries::get_next_goal_position(&mut conn, group.id).await?;
 
-    sqlx::query!(
-        "INSERT INTO goals(title, description, stage, deadline, group_id) 
-        VALUES ($1, $2, $3, $4, $5)",
+    let goal_id = sqlx::query!(
+        "INSERT INTO goals(title, description, stage, deadline, priority, group_id, created_by, position) 
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
         form.title,
         form.description,
         form.stage,
         form.deadline,
+        form.priority,
         group.id,
+        user.id,
+        position,
     )
     .execute(&mut conn)
     .await
-    .map_err(ErrorInternalServerError)?;
+    .map_err(ErrorInternalServerError)?
+    .last_insert_rowid();
+
+    queries::set_goal_labels(&mut conn, user.id, goal_id, &form.labels).await?;
 
     if *is_hx {
         let group = queries::get_group_with_info(&mut conn, user.id, group.id).await?;
         let goals = queries::get_goals_for_group(&mut conn, group.id).await?;
-        let goals_in_stages = group_goals_by_stage(&goals);
+        let goals_in_stages = group_goals_by_stage(&goals, group.goal_sort);
 
         let notification = hx_trigger_notification(
             format!("Created {}", form.title),
@@ -644,18 +1117,7 @@ async fn get_goal(
     let group = queries::get_group_with_info(&mut conn, user.id, group_id).await?;
 
     if *is_hx {
-        let goal = sqlx::query_as!(
-            Goal,
-            "SELECT * FROM goals WHERE id = $1 AND group_id = $2;",
-            goal_id,
-            group_id,
-        )
-        .fetch_one(&mut conn)
-        .await
-        .map_err(|err| match err {
-            sqlx::Error::RowNotFound => ErrorNotFound(err),
-            _ => ErrorInternalServerError(err),
-        })?;
+        let goal = queries::get_goal(&mut conn, group_id, goal_id).await?;
 
         let body = ShowGoalPartial {
             goal,
@@ -668,7 +1130,7 @@ async fn get_goal(
     }
 
     let goals = queries::get_goals_for_group(&mut conn, group_id).await?;
-    let goals_in_stages = group_goals_by_stage(&goals);
+    let goals_in_stages = group_goals_by_stage(&goals, group.goal_sort);
 
     let goal = goals.iter().find(|g| g.id == goal_id);
 
@@ -714,23 +1176,18 @@ async fn edit_goal(
     let user = queries::get_user_from_identity(&mut conn, &identity).await?;
 
     let group = queries::get_group_with_info(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::EditGoals)?;
+    let labels = queries::get_labels(&mut conn, user.id).await?;
     let csrf_token = CsrfToken::get_or_create(&session)?;
 
     if *is_hx {
-        let goal = sqlx::query_as!(
-            Goal,
-            "SELECT * FROM goals WHERE id = $1 AND group_id = $2",
-            goal_id,
-            group_id,
-        )
-        .fetch_one(&mut conn)
-        .await
-        .map_err(ErrorInternalServerError)?;
+        let goal = queries::get_goal(&mut conn, group_id, goal_id).await?;
 
         let body = EditGoalPartial {
             goal,
             group: group.into(),
             csrf_token,
+            labels,
         }
         .render()
         .map_err(ErrorInternalServerError)?;
@@ -739,7 +1196,7 @@ async fn edit_goal(
     }
 
     let goals = queries::get_goals_for_group(&mut conn, group_id).await?;
-    let goals_in_stages = group_goals_by_stage(&goals);
+    let goals_in_stages = group_goals_by_stage(&goals, group.goal_sort);
 
     let goal = goals.iter().find(|g| g.id == goal_id);
 
@@ -761,6 +1218,7 @@ async fn edit_goal(
         csrf_token,
         goal,
         groups,
+        labels,
     }
     .render()
     .map_err(ErrorInternalServerError)?;
@@ -774,6 +1232,10 @@ struct EditGoalForm {
     description: Option<String>,
     deadline: Option<chrono::NaiveDate>,
     stage: i16,
+    #[serde(default)]
+    priority: Priority,
+    #[serde(default)]
+    labels: Vec<i64>,
     csrftoken: String,
 }
 
@@ -781,7 +1243,7 @@ struct EditGoalForm {
 async fn post_edit_goal(
     identity: Identity,
     path: web::Path<(i64, i64)>,
-    form: web::Form<EditGoalForm>,
+    form: HtmlForm<EditGoalForm>,
     session: Session,
     pool: web::Data<SqlitePool>,
     is_hx: IsHtmx,
@@ -797,30 +1259,35 @@ async fn post_edit_goal(
 
     let user = queries::get_user_from_identity(&mut conn, &identity).await?;
 
-    let group = sqlx::query_as!(
-        Group,
-        r#"SELECT * FROM groups 
-        WHERE user_id = $1 AND id = $2;"#,
+    let group = queries::get_group(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::EditGoals)?;
+
+    // Remember who moved the goal if its stage is changing
+    sqlx::query!(
+        "UPDATE goals
+        SET (moved_by, moved_at) = ($1, CURRENT_TIMESTAMP)
+        WHERE
+        id = $2 AND group_id = $3 AND stage != $4 AND deleted_at IS NULL;",
         user.id,
-        group_id
+        goal_id,
+        group.id,
+        form.stage,
     )
-    .fetch_one(&mut conn)
+    .execute(&mut conn)
     .await
-    .map_err(|err| match err {
-        sqlx::Error::RowNotFound => ErrorNotFound(err),
-        e => ErrorInternalServerError(e),
-    })?;
+    .map_err(ErrorInternalServerError)?;
 
-    sqlx::query!(
+    let result = sqlx::query!(
         "UPDATE goals
-        SET (title, description, stage, deadline) =
-        ($1, $2, $3, $4)
+        SET (title, description, stage, deadline, priority) =
+        ($1, $2, $3, $4, $5)
         WHERE 
-        id = $5 AND group_id = $6;",
+        id = $6 AND group_id = $7 AND deleted_at IS NULL;",
         form.title,
         form.description,
         form.stage,
         form.deadline,
+        form.priority,
         goal_id,
         group.id,
     )
@@ -828,10 +1295,16 @@ async fn post_edit_goal(
     .await
     .map_err(ErrorInternalServerError)?;
 
+    if result.rows_affected() == 0 {
+        return Err(ErrorNotFound("Goal not found"));
+    }
+
+    queries::set_goal_labels(&mut conn, user.id, goal_id, &form.labels).await?;
+
     if *is_hx {
         let group = queries::get_group_with_info(&mut conn, user.id, group.id).await?;
         let goals = queries::get_goals_for_group(&mut conn, group.id).await?;
-        let goals_in_stages = group_goals_by_stage(&goals);
+        let goals_in_stages = group_goals_by_stage(&goals, group.goal_sort);
         let notification = hx_trigger_notification(
             format!("{} updated", form.title),
             "Your goal was updated".into(),
@@ -854,6 +1327,183 @@ async fn post_edit_goal(
         .finish())
 }
 
+#[get("/groups/{group_id}/goals/{goal_id}/move")]
+async fn move_goal(
+    identity: Identity,
+    path: web::Path<(i64, i64)>,
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    is_hx: IsHtmx,
+) -> actix_web::Result<HttpResponse> {
+    let (group_id, goal_id) = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let group = queries::get_group_with_info(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::EditGoals)?;
+    let groups = queries::get_group_links(&mut conn, user.id).await?;
+    let targets: Vec<GroupLink> = groups
+        .iter()
+        .filter(|link| link.takes_goals_from(group_id))
+        .cloned()
+        .collect();
+    let csrf_token = CsrfToken::get_or_create(&session)?;
+
+    if *is_hx {
+        let goal = queries::get_goal(&mut conn, group_id, goal_id).await?;
+
+        let body = MoveGoalPartial {
+            goal,
+            group: group.into(),
+            csrf_token,
+            targets,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?;
+
+        return Ok(HttpResponse::Ok().body(body));
+    }
+
+    let goals = queries::get_goals_for_group(&mut conn, group_id).await?;
+    let goals_in_stages = group_goals_by_stage(&goals, group.goal_sort);
+
+    let goal = goals
+        .iter()
+        .find(|g| g.id == goal_id)
+        .cloned()
+        .ok_or_else(|| ErrorNotFound("Goal not found"))?;
+
+    let body = MoveGoalPage {
+        title: "Silly Goals".into(),
+        user,
+        group: group.into(),
+        goals_in_stages,
+        csrf_token,
+        goal,
+        groups,
+        targets,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+#[derive(Deserialize)]
+struct MoveGoalForm {
+    group_id: i64,
+    csrftoken: String,
+}
+
+/// Move a goal to another of the user's groups, fitting its stage and deadline
+/// to the new group's tone
+#[post("/groups/{group_id}/goals/{goal_id}/move")]
+async fn post_move_goal(
+    identity: Identity,
+    path: web::Path<(i64, i64)>,
+    form: web::Form<MoveGoalForm>,
+    session: Session,
+    pool: web::Data<SqlitePool>,
+    is_hx: IsHtmx,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let (group_id, goal_id) = path.into_inner();
+
+    if form.group_id == group_id {
+        return Err(ErrorBadRequest("Goal is already in this group"));
+    }
+
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let from_group = queries::get_group_with_info(&mut conn, user.id, group_id).await?;
+    let to_group = queries::get_group_with_info(&mut conn, user.id, form.group_id).await?;
+    from_group.role.require(Permission::EditGoals)?;
+    to_group.role.require(Permission::EditGoals)?;
+    let goal = queries::get_goal(&mut conn, from_group.id, goal_id).await?;
+
+    let stage = remap_stage(
+        goal.stage,
+        from_group.tone_stages.len(),
+        to_group.tone_stages.len(),
+    );
+    let deadline = match to_group.deadline {
+        DeadlineType::Off => None,
+        _ => goal.deadline.clone(),
+    };
+
+    let position = queries::get_next_goal_position(&mut conn, to_group.id).await?;
+
+    let result = sqlx::query!(
+        "UPDATE goals
+        SET (group_id, stage, deadline, position, moved_by, moved_at) = ($1, $2, $3, $4, $5, CURRENT_TIMESTAMP)
+        WHERE
+        id = $6 AND group_id = $7 AND deleted_at IS NULL;",
+        to_group.id,
+        stage,
+        deadline,
+        position,
+        user.id,
+        goal.id,
+        from_group.id,
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    if result.rows_affected() == 0 {
+        return Err(ErrorNotFound("Goal not found"));
+    }
+
+    if *is_hx {
+        let message = if goal.deadline.is_some() && deadline.is_none() {
+            format!(
+                "Moved to {}, which doesn't use deadlines so the deadline was removed",
+                to_group.title
+            )
+        } else {
+            format!("Moved to {}", to_group.title)
+        };
+        let notification = hx_trigger_notification(
+            format!("{} moved", goal.title),
+            message,
+            NotificationVariant::Success,
+            true,
+        );
+
+        let goals = queries::get_goals_for_group(&mut conn, to_group.id).await?;
+        let goals_in_stages = group_goals_by_stage(&goals, to_group.goal_sort);
+        let location = format!("/groups/{}", to_group.id);
+
+        let body = ShowGroupPartial {
+            group: to_group.into(),
+            goals_in_stages,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?;
+
+        return Ok(HttpResponse::Ok()
+            .append_header(notification)
+            .insert_header(("HX-Push-Url", location))
+            .append_header(("HX-Trigger-After-Settle", "updateLocation"))
+            .body(body));
+    }
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/groups/{}", form.group_id)))
+        .finish())
+}
+
 #[derive(Debug, Deserialize)]
 struct NewStage {
     stage: i64,
@@ -877,17 +1527,31 @@ async fn patch_goal_tone(
     let user = queries::get_user_from_identity(&mut conn, &identity).await?;
 
     let group = queries::get_group_with_info(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::EditGoals)?;
 
     if query.stage > 4 || query.stage < 0 {
         return Err(ErrorBadRequest("Stage must be between 0 and 4"));
     }
 
+    // the card is dropped at the top of its new stage
+    let position = sqlx::query_scalar!(
+        r#"SELECT COALESCE(MIN(position), 1) - 1 as "position!: i64" FROM goals
+        WHERE group_id = $1 AND stage = $2 AND deleted_at IS NULL;"#,
+        group_id,
+        query.stage,
+    )
+    .fetch_one(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
     sqlx::query!(
         "UPDATE goals
-        SET stage = $1 
+        SET (stage, position, moved_by, moved_at) = ($1, $2, $3, CURRENT_TIMESTAMP)
         WHERE 
-        id = $2 AND group_id = $3;",
+        id = $4 AND group_id = $5 AND deleted_at IS NULL;",
         query.stage,
+        position,
+        user.id,
         goal_id,
         group_id,
     )
@@ -898,15 +1562,7 @@ async fn patch_goal_tone(
         ErrorInternalServerError(err)
     })?;
 
-    let goal = sqlx::query_as!(
-        Goal,
-        "SELECT * FROM goals WHERE id = $1 AND group_id = $2",
-        goal_id,
-        group_id
-    )
-    .fetch_one(&mut conn)
-    .await
-    .map_err(ErrorInternalServerError)?;
+    let goal = queries::get_goal(&mut conn, group_id, goal_id).await?;
 
     let body = SingleGoalCard {
         goal,
@@ -919,13 +1575,66 @@ async fn patch_goal_tone(
     Ok(HttpResponse::Ok().body(body))
 }
 
-#[delete("/groups/{group_id}/goals/{goal_id}")]
-async fn delete_goal(
+#[derive(Debug, Deserialize)]
+struct GoalOrderForm {
+    #[serde(default)]
+    goals: Vec<i64>,
+}
+
+/// Save the order goals were dragged into within a stage, used by the manual
+/// sort
+#[patch("/groups/{id}/goal-order")]
+async fn patch_goal_order(
+    identity: Identity,
+    path: web::Path<i64>,
+    form: HtmlForm<GoalOrderForm>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let group_id = path.into_inner();
+
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let group = queries::get_group(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::EditGoals)?;
+
+    let mut tx = conn.begin().await.map_err(ErrorInternalServerError)?;
+    for (position, goal_id) in (1_i64..).zip(form.goals.iter()) {
+        sqlx::query!(
+            "UPDATE goals SET position = $1 WHERE id = $2 AND group_id = $3 AND deleted_at IS NULL;",
+            position,
+            goal_id,
+            group.id
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    }
+    tx.commit().await.map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(Deserialize)]
+struct NewDeadline {
+    deadline: chrono::NaiveDate,
+}
+
+/// Reschedule a goal from the calendar
+#[patch("/groups/{group_id}/goals/{goal_id}/deadline")]
+async fn patch_goal_deadline(
     identity: Identity,
     path: web::Path<(i64, i64)>,
+    form: web::Form<NewDeadline>,
     pool: web::Data<SqlitePool>,
 ) -> actix_web::Result<HttpResponse> {
     let (group_id, goal_id) = path.into_inner();
+
     let mut conn = pool
         .get_ref()
         .acquire()
@@ -934,23 +1643,67 @@ async fn delete_goal(
 
     let user = queries::get_user_from_identity(&mut conn, &identity).await?;
 
-    sqlx::query!(
-        r#"SELECT 
-        id
-        FROM groups 
-        WHERE user_id = $1 AND id = $2;"#,
-        user.id,
-        group_id
+    let group = queries::get_group_with_info(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::EditGoals)?;
+
+    if group.deadline == DeadlineType::Off {
+        return Err(ErrorBadRequest("This group does not use deadlines"));
+    }
+
+    let result = sqlx::query!(
+        "UPDATE goals
+        SET deadline = $1
+        WHERE
+        id = $2 AND group_id = $3 AND deleted_at IS NULL;",
+        form.deadline,
+        goal_id,
+        group_id,
     )
-    .fetch_one(&mut conn)
+    .execute(&mut conn)
     .await
-    .map_err(|err| match err {
-        sqlx::Error::RowNotFound => ErrorNotFound(err),
-        e => ErrorInternalServerError(e),
+    .map_err(|err| {
+        error!("Could not update database");
+        ErrorInternalServerError(err)
     })?;
 
+    if result.rows_affected() == 0 {
+        return Err(ErrorNotFound("Goal not found"));
+    }
+
+    let notification = hx_trigger_notification(
+        "Goal Rescheduled".into(),
+        format!("Now due {}", form.deadline.format("%B %-d, %Y")),
+        NotificationVariant::Success,
+        true,
+    );
+
+    Ok(HttpResponse::Ok()
+        .append_header(notification)
+        .append_header(("HX-Trigger", "calendarChanged"))
+        .finish())
+}
+
+#[delete("/groups/{group_id}/goals/{goal_id}")]
+async fn delete_goal(
+    identity: Identity,
+    path: web::Path<(i64, i64)>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let (group_id, goal_id) = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let group = queries::get_group(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::EditGoals)?;
+
     sqlx::query!(
-        "DELETE FROM goals WHERE group_id = $1 AND id = $2",
+        "UPDATE goals SET deleted_at = CURRENT_TIMESTAMP
+        WHERE group_id = $1 AND id = $2 AND deleted_at IS NULL",
         group_id,
         goal_id
     )
@@ -979,6 +1732,7 @@ async fn group_edit_group(
     let user = queries::get_user_from_identity(&mut conn, &identity).await?;
 
     let group = queries::get_group_with_info(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::ManageGroup)?;
 
     let csrf_token = CsrfToken::get_or_create(&session)?;
 
@@ -1013,7 +1767,7 @@ async fn group_edit_group(
 
     let goals = queries::get_goals_for_group(&mut conn, group_id).await?;
 
-    let goals_in_stages = group_goals_by_stage(&goals);
+    let goals_in_stages = group_goals_by_stage(&goals, group.goal_sort);
 
     let body = GroupEditGroupPage {
         title: "Silly Goals".into(),
@@ -1138,3 +1892,25 @@ async fn dashboard_help_tones(
 
     Ok(HttpResponse::Ok().body(body))
 }
+
+#[derive(Deserialize)]
+struct MarkdownPreviewForm {
+    description: Option<String>,
+    csrftoken: String,
+}
+
+/// Render a description the way it will look once saved, for the preview tab
+/// in the goal and group forms
+#[post("/markdown/preview")]
+async fn markdown_preview(
+    _identity: Identity,
+    form: HtmlForm<MarkdownPreviewForm>,
+    session: Session,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let body = match form.description.as_deref().map(markdown::render) {
+        Some(html) if !html.trim().is_empty() => html,
+        _ => "<p class=\"text-zinc-500 italic\">Nothing to preview</p>".into(),
+    };
+    Ok(HttpResponse::Ok().body(body))
+}
@@ -0,0 +1,223 @@
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{
+    delete,
+    error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound},
+    get, post, web, HttpResponse,
+};
+use askama::Template;
+use serde::Deserialize;
+use sqlx::{pool::PoolConnection, Sqlite, SqlitePool};
+
+use crate::{
+    csrf_token::CsrfToken,
+    htmx::{hx_trigger_notification, HxHeaderInfo, IsHtmx, NotificationVariant},
+    queries,
+    templates::*,
+    LabelColor,
+};
+
+fn is_unique_violation(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Database(e) => e.message().contains("UNIQUE"),
+        _ => false,
+    }
+}
+
+async fn render_labels(
+    conn: &mut PoolConnection<Sqlite>,
+    session: &Session,
+    user_id: i64,
+) -> actix_web::Result<String> {
+    let labels = queries::get_labels(conn, user_id).await?;
+    let csrf_token = CsrfToken::get_or_create(session)?;
+    LabelsPartial { labels, csrf_token }
+        .render()
+        .map_err(ErrorInternalServerError)
+}
+
+/// Manage the labels that can be put on goals
+#[get("/labels")]
+async fn labels_page(
+    identity: Identity,
+    session: Session,
+    pool: web::Data<SqlitePool>,
+    is_hx: IsHtmx,
+    hx_headers: HxHeaderInfo,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let labels = queries::get_labels(&mut conn, user.id).await?;
+    let csrf_token = CsrfToken::get_or_create(&session)?;
+
+    let body = if *is_hx && !hx_headers.boosted {
+        LabelsPartial { labels, csrf_token }
+            .render()
+            .map_err(ErrorInternalServerError)?
+    } else {
+        let groups = queries::get_group_links(&mut conn, user.id).await?;
+        LabelsPage {
+            title: "Silly Goals".into(),
+            user,
+            groups,
+            labels,
+            csrf_token,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?
+    };
+    Ok(HttpResponse::Ok()
+        .insert_header(("HX-Trigger-After-Swap", "updateLocation"))
+        .body(body))
+}
+
+#[derive(Deserialize)]
+struct LabelForm {
+    name: String,
+    color: LabelColor,
+    csrftoken: String,
+}
+
+#[post("/labels")]
+async fn post_new_label(
+    identity: Identity,
+    session: Session,
+    pool: web::Data<SqlitePool>,
+    form: web::Form<LabelForm>,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Err(ErrorBadRequest("Labels need a name"));
+    }
+    let notification = match sqlx::query!(
+        "INSERT INTO labels(name, color, user_id) VALUES ($1, $2, $3);",
+        name,
+        form.color,
+        user.id
+    )
+    .execute(&mut conn)
+    .await
+    {
+        Ok(_) => hx_trigger_notification(
+            "Label Created".into(),
+            format!("You can now add {} to your goals", name),
+            NotificationVariant::Success,
+            true,
+        ),
+        Err(err) if is_unique_violation(&err) => hx_trigger_notification(
+            "Label Not Created".into(),
+            format!("You already have a label called {}", name),
+            NotificationVariant::Failure,
+            true,
+        ),
+        Err(err) => return Err(ErrorInternalServerError(err)),
+    };
+
+    let body = render_labels(&mut conn, &session, user.id).await?;
+
+    Ok(HttpResponse::Ok().append_header(notification).body(body))
+}
+
+#[post("/labels/{id}/edit")]
+async fn post_edit_label(
+    identity: Identity,
+    session: Session,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+    form: web::Form<LabelForm>,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let label_id = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Err(ErrorBadRequest("Labels need a name"));
+    }
+    let notification = match sqlx::query!(
+        "UPDATE labels SET (name, color) = ($1, $2) WHERE id = $3 AND user_id = $4;",
+        name,
+        form.color,
+        label_id,
+        user.id
+    )
+    .execute(&mut conn)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => {
+            return Err(ErrorNotFound("Label not found"));
+        }
+        Ok(_) => hx_trigger_notification(
+            "Label Updated".into(),
+            format!("Your label {} was updated", name),
+            NotificationVariant::Success,
+            true,
+        ),
+        Err(err) if is_unique_violation(&err) => hx_trigger_notification(
+            "Label Not Updated".into(),
+            format!("You already have a label called {}", name),
+            NotificationVariant::Failure,
+            true,
+        ),
+        Err(err) => return Err(ErrorInternalServerError(err)),
+    };
+
+    let body = render_labels(&mut conn, &session, user.id).await?;
+
+    Ok(HttpResponse::Ok().append_header(notification).body(body))
+}
+
+/// Delete a label and take it off any goals that have it
+#[delete("/labels/{id}")]
+async fn delete_label(
+    identity: Identity,
+    session: Session,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let label_id = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    sqlx::query!(
+        "DELETE FROM labels WHERE id = $1 AND user_id = $2;",
+        label_id,
+        user.id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    let notification = hx_trigger_notification(
+        "Label Deleted".into(),
+        "The label was removed from all your goals".into(),
+        NotificationVariant::Success,
+        true,
+    );
+
+    let body = render_labels(&mut conn, &session, user.id).await?;
+
+    Ok(HttpResponse::Ok().append_header(notification).body(body))
+}
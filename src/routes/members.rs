@@ -0,0 +1,556 @@
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{
+    delete,
+    error::{ErrorBadRequest, ErrorForbidden, ErrorInternalServerError, ErrorNotFound},
+    get, patch, post, web, HttpResponse,
+};
+use askama::Template;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use log::error;
+use serde::Deserialize;
+use sqlx::{pool::PoolConnection, Sqlite, SqlitePool};
+
+use crate::{
+    csrf_token::CsrfToken,
+    html_form::HtmlForm,
+    htmx::{hx_trigger_notification, HxHeaderInfo, IsHtmx, NotificationVariant},
+    login_links::hash_token,
+    mail::build_email_for_user,
+    members::{new_invitation_token, GroupRole, PendingInvitation, Permission, INVITATION_DAYS},
+    queries,
+    templates::*,
+    User,
+};
+
+async fn render_members(
+    conn: &mut PoolConnection<Sqlite>,
+    session: &Session,
+    user: &User,
+    group_id: i64,
+) -> actix_web::Result<String> {
+    let group = queries::get_group(conn, user.id, group_id).await?;
+    let members = queries::get_group_members(conn, group.id).await?;
+    let invitations = queries::get_group_invitations(conn, group.id).await?;
+    let csrf_token = CsrfToken::get_or_create(session)?;
+    GroupMembersPartial {
+        group,
+        members,
+        invitations,
+        csrf_token,
+        user_id: user.id,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)
+}
+
+/// Everyone who can see a group, and the invitations that haven't been
+/// answered yet
+#[get("/groups/{id}/members")]
+async fn group_members(
+    identity: Identity,
+    session: Session,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+    is_hx: IsHtmx,
+    hx_headers: HxHeaderInfo,
+) -> actix_web::Result<HttpResponse> {
+    let group_id = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let body = if *is_hx && !hx_headers.boosted {
+        render_members(&mut conn, &session, &user, group_id).await?
+    } else {
+        let group = queries::get_group(&mut conn, user.id, group_id).await?;
+        let members = queries::get_group_members(&mut conn, group.id).await?;
+        let invitations = queries::get_group_invitations(&mut conn, group.id).await?;
+        let csrf_token = CsrfToken::get_or_create(&session)?;
+        let groups = queries::get_group_links(&mut conn, user.id).await?;
+        GroupMembersPage {
+            title: "Silly Goals".into(),
+            user_id: user.id,
+            user,
+            groups,
+            group,
+            members,
+            invitations,
+            csrf_token,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?
+    };
+    Ok(HttpResponse::Ok()
+        .insert_header(("HX-Trigger-After-Swap", "updateLocation"))
+        .body(body))
+}
+
+#[derive(Deserialize)]
+struct InviteForm {
+    email: String,
+    role: GroupRole,
+    csrftoken: String,
+}
+
+/// Invite someone to a group by email. Inviting the same address again sends
+/// a fresh link and replaces the old one.
+#[post("/groups/{id}/members/invite")]
+async fn post_invite_member(
+    identity: Identity,
+    session: Session,
+    path: web::Path<i64>,
+    form: HtmlForm<InviteForm>,
+    pool: web::Data<SqlitePool>,
+    mailer: web::Data<AsyncSmtpTransport<Tokio1Executor>>,
+    hostname: web::Data<String>,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let group_id = path.into_inner();
+    if !GroupRole::invitable().contains(&form.role) {
+        return Err(ErrorBadRequest("Groups can only have one owner"));
+    }
+
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let group = queries::get_group(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::ManageGroup)?;
+
+    let email = form.email.trim().to_lowercase();
+
+    let already_member = sqlx::query!(
+        "SELECT m.user_id FROM group_members m
+        INNER JOIN users u
+        ON u.id = m.user_id
+        WHERE m.group_id = $1 AND u.email = $2;",
+        group.id,
+        email
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .is_some();
+
+    let notification = if already_member {
+        hx_trigger_notification(
+            "Not Invited".into(),
+            format!("{} is already in {}", email, group.title),
+            NotificationVariant::Failure,
+            true,
+        )
+    } else {
+        let token = new_invitation_token();
+        let inviter = user.name.clone().unwrap_or_else(|| user.email.clone());
+        let message = build_email_for_user(
+            &email,
+            &format!("{} shared {} with you on Silly Goals", inviter, group.title),
+            &format!(
+                "{} invited you to join their group {} on Silly Goals, where you'll be \
+                able to {}.\n\n\
+                Accept or decline the invitation here: https://{}/invitations/{}\n\n\
+                If you don't have an account yet, register with this email address \
+                first. The invitation can be used for {} days.",
+                inviter,
+                group.title,
+                form.role.description(),
+                hostname.as_ref(),
+                token,
+                INVITATION_DAYS
+            ),
+        )?;
+
+        let token_hash = hash_token(&token);
+        sqlx::query!(
+            "INSERT INTO group_invitations(group_id, email, role, token_hash, invited_by)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT(group_id, email) DO UPDATE SET
+            role = excluded.role,
+            token_hash = excluded.token_hash,
+            invited_by = excluded.invited_by,
+            created_at = CURRENT_TIMESTAMP;",
+            group.id,
+            email,
+            form.role,
+            token_hash,
+            user.id
+        )
+        .execute(&mut conn)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+        tokio::spawn(async move {
+            match mailer.send(message).await {
+                Ok(_) => (),
+                Err(e) => {
+                    error!("Could not send group invitation: {}", e);
+                }
+            }
+        });
+
+        hx_trigger_notification(
+            "Invitation Sent".into(),
+            format!("We emailed an invitation to {}", email),
+            NotificationVariant::Success,
+            true,
+        )
+    };
+
+    let body = render_members(&mut conn, &session, &user, group.id).await?;
+
+    Ok(HttpResponse::Ok().append_header(notification).body(body))
+}
+
+#[derive(Deserialize)]
+struct RoleForm {
+    role: GroupRole,
+}
+
+/// Change what a member can do. The owner's role can't be changed.
+#[patch("/groups/{id}/members/{user_id}")]
+async fn patch_member_role(
+    identity: Identity,
+    session: Session,
+    path: web::Path<(i64, i64)>,
+    form: web::Form<RoleForm>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let (group_id, member_id) = path.into_inner();
+    if !GroupRole::invitable().contains(&form.role) {
+        return Err(ErrorBadRequest("Groups can only have one owner"));
+    }
+
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let group = queries::get_group(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::ManageGroup)?;
+
+    let updated = sqlx::query!(
+        "UPDATE group_members SET role = $1
+        WHERE group_id = $2 AND user_id = $3 AND role != 'owner';",
+        form.role,
+        group.id,
+        member_id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .rows_affected();
+
+    if updated == 0 {
+        return Err(ErrorNotFound("Member not found"));
+    }
+
+    let notification = hx_trigger_notification(
+        "Role Changed".into(),
+        format!("They are now {}", form.role.name().to_lowercase()),
+        NotificationVariant::Success,
+        true,
+    );
+
+    let body = render_members(&mut conn, &session, &user, group.id).await?;
+
+    Ok(HttpResponse::Ok().append_header(notification).body(body))
+}
+
+/// Take someone out of a group. The owner can remove anyone else, and any
+/// other member can remove themselves to leave the group.
+#[delete("/groups/{id}/members/{user_id}")]
+async fn delete_member(
+    identity: Identity,
+    session: Session,
+    path: web::Path<(i64, i64)>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let (group_id, member_id) = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let group = queries::get_group(&mut conn, user.id, group_id).await?;
+    let leaving = member_id == user.id;
+    if leaving && group.role.can_manage() {
+        return Err(ErrorForbidden(
+            "The owner can't leave their own group. Delete it instead.",
+        ));
+    }
+    if !leaving {
+        group.role.require(Permission::ManageGroup)?;
+    }
+
+    let removed = sqlx::query!(
+        "DELETE FROM group_members
+        WHERE group_id = $1 AND user_id = $2 AND role != 'owner';",
+        group.id,
+        member_id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .rows_affected();
+
+    if removed == 0 {
+        return Err(ErrorNotFound("Member not found"));
+    }
+
+    if leaving {
+        // The group is gone from the sidebar too, so load the whole dashboard
+        return Ok(HttpResponse::Ok()
+            .insert_header(("HX-Redirect", "/dashboard"))
+            .finish());
+    }
+
+    let notification = hx_trigger_notification(
+        "Member Removed".into(),
+        format!("They can no longer see {}", group.title),
+        NotificationVariant::Success,
+        true,
+    );
+
+    let body = render_members(&mut conn, &session, &user, group.id).await?;
+
+    Ok(HttpResponse::Ok().append_header(notification).body(body))
+}
+
+/// Cancel an invitation so its link stops working
+#[delete("/groups/{id}/invitations/{invitation_id}")]
+async fn delete_invitation(
+    identity: Identity,
+    session: Session,
+    path: web::Path<(i64, i64)>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let (group_id, invitation_id) = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let group = queries::get_group(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::ManageGroup)?;
+
+    sqlx::query!(
+        "DELETE FROM group_invitations WHERE id = $1 AND group_id = $2;",
+        invitation_id,
+        group.id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    let notification = hx_trigger_notification(
+        "Invitation Cancelled".into(),
+        "The invitation link no longer works".into(),
+        NotificationVariant::Success,
+        true,
+    );
+
+    let body = render_members(&mut conn, &session, &user, group.id).await?;
+
+    Ok(HttpResponse::Ok().append_header(notification).body(body))
+}
+
+/// Invitations can only be answered by the account they were sent to
+fn check_invitation_email(invitation_email: &str, user: &User) -> actix_web::Result<()> {
+    if invitation_email != user.email.to_lowercase() {
+        return Err(ErrorForbidden(
+            "This invitation was sent to a different email address",
+        ));
+    }
+    Ok(())
+}
+
+/// Show an invitation, as a partial for htmx or as the whole page
+async fn render_invitation(
+    conn: &mut PoolConnection<Sqlite>,
+    session: &Session,
+    user: User,
+    invitation: PendingInvitation,
+    partial: bool,
+) -> actix_web::Result<HttpResponse> {
+    let csrf_token = CsrfToken::get_or_create(session)?;
+    let body = if partial {
+        InvitationPartial {
+            invitation,
+            csrf_token,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?
+    } else {
+        let groups = queries::get_group_links(conn, user.id).await?;
+        InvitationPage {
+            title: "Silly Goals".into(),
+            user,
+            groups,
+            invitation,
+            csrf_token,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?
+    };
+    Ok(HttpResponse::Ok()
+        .insert_header(("HX-Trigger-After-Swap", "updateLocation"))
+        .body(body))
+}
+
+/// Where someone lands from an invitation email to accept or decline it
+#[get("/invitations/{token}")]
+async fn show_invitation(
+    identity: Identity,
+    session: Session,
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+    is_hx: IsHtmx,
+    hx_headers: HxHeaderInfo,
+) -> actix_web::Result<HttpResponse> {
+    let token_hash = hash_token(&path.into_inner());
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let invitation = queries::get_invitation(&mut conn, &token_hash).await?;
+    check_invitation_email(&invitation.email, &user)?;
+
+    render_invitation(
+        &mut conn,
+        &session,
+        user,
+        invitation,
+        *is_hx && !hx_headers.boosted,
+    )
+    .await
+}
+
+/// An invitation waiting for the user, opened from the dashboard
+#[get("/groups/{id}/invitation")]
+async fn show_group_invitation(
+    identity: Identity,
+    session: Session,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+    is_hx: IsHtmx,
+    hx_headers: HxHeaderInfo,
+) -> actix_web::Result<HttpResponse> {
+    let group_id = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let invitation = queries::get_group_invitation(&mut conn, group_id, &user.email).await?;
+
+    render_invitation(
+        &mut conn,
+        &session,
+        user,
+        invitation,
+        *is_hx && !hx_headers.boosted,
+    )
+    .await
+}
+
+#[derive(Deserialize)]
+struct InvitationForm {
+    csrftoken: String,
+}
+
+#[post("/groups/{id}/invitation/accept")]
+async fn accept_invitation(
+    identity: Identity,
+    session: Session,
+    path: web::Path<i64>,
+    form: web::Form<InvitationForm>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let group_id = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let invitation = queries::get_group_invitation(&mut conn, group_id, &user.email).await?;
+
+    let position = queries::get_next_group_position(&mut conn, user.id).await?;
+
+    sqlx::query!(
+        "INSERT INTO group_members(group_id, user_id, role, position)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT(group_id, user_id) DO NOTHING;",
+        invitation.group_id,
+        user.id,
+        invitation.role,
+        position
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    sqlx::query!(
+        "DELETE FROM group_invitations WHERE id = $1;",
+        invitation.id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/groups/{}", invitation.group_id)))
+        .finish())
+}
+
+#[post("/groups/{id}/invitation/decline")]
+async fn decline_invitation(
+    identity: Identity,
+    session: Session,
+    path: web::Path<i64>,
+    form: web::Form<InvitationForm>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let group_id = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let invitation = queries::get_group_invitation(&mut conn, group_id, &user.email).await?;
+
+    sqlx::query!(
+        "DELETE FROM group_invitations WHERE id = $1;",
+        invitation.id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header(("Location", "/dashboard"))
+        .finish())
+}
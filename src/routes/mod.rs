@@ -1,3 +1,15 @@
+pub mod archive;
 pub mod auth;
+pub mod bulk;
+pub mod calendar_feed;
 pub mod dashboard;
+pub mod labels;
+pub mod members;
+pub mod passkeys;
+pub mod reauth;
+pub mod recovery;
+pub mod security;
+pub mod sessions;
+pub mod share;
+pub mod trash;
 pub mod webauthn_routes;
@@ -0,0 +1,116 @@
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{error::ErrorInternalServerError, get, post, web, HttpRequest, HttpResponse};
+use askama::Template;
+use serde::Deserialize;
+use sqlx::{pool::PoolConnection, types::Uuid, Sqlite, SqlitePool};
+
+use crate::{
+    csrf_token::CsrfToken,
+    htmx::{hx_trigger_notification, NotificationVariant},
+    queries,
+    reauth::require_recent_auth,
+    security_events::{record_security_event, SecurityEventKind},
+    templates::*,
+    SavedPasskey, User,
+};
+
+async fn render_passkeys(
+    conn: &mut PoolConnection<Sqlite>,
+    session: &Session,
+    user: &User,
+) -> actix_web::Result<String> {
+    let passkeys = sqlx::query_as!(
+        SavedPasskey,
+        r#"SELECT id as "id: Uuid", credential_id
+        FROM webauthn_credentials
+        WHERE user_id = $1
+        ORDER BY rowid;"#,
+        user.id
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+    let csrf_token = CsrfToken::get_or_create(session)?;
+    PasskeysPartial {
+        passkeys,
+        csrf_token,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)
+}
+
+/// The user's passkeys, shown on the profile page
+#[get("/profile/passkeys")]
+async fn profile_passkeys(
+    identity: Identity,
+    session: Session,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let body = render_passkeys(&mut conn, &session, &user).await?;
+    Ok(HttpResponse::Ok().body(body))
+}
+
+#[derive(Deserialize)]
+struct RemovePasskeyForm {
+    csrftoken: String,
+}
+
+/// Stop a passkey from logging in to the account
+#[post("/profile/passkeys/{id}/remove")]
+async fn remove_passkey(
+    req: HttpRequest,
+    identity: Identity,
+    session: Session,
+    path: web::Path<Uuid>,
+    form: web::Form<RemovePasskeyForm>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let id = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+    require_recent_auth(&mut conn, &req, &identity).await?;
+
+    let removed = sqlx::query_as!(
+        SavedPasskey,
+        r#"DELETE FROM webauthn_credentials WHERE id = $1 AND user_id = $2
+        RETURNING id as "id!: Uuid", credential_id;"#,
+        id,
+        user.id
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+    if let Some(removed) = removed {
+        record_security_event(
+            &mut conn,
+            &req,
+            user.id,
+            SecurityEventKind::PasskeyRemoved,
+            Some(&removed.short_id()),
+        )
+        .await?;
+    }
+
+    let notification = hx_trigger_notification(
+        "Passkey Removed".into(),
+        "That passkey can no longer be used to log in".into(),
+        NotificationVariant::Success,
+        true,
+    );
+
+    let body = render_passkeys(&mut conn, &session, &user).await?;
+    Ok(HttpResponse::Ok().append_header(notification).body(body))
+}
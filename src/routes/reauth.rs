@@ -0,0 +1,196 @@
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{error::ErrorInternalServerError, get, post, web, HttpRequest, HttpResponse};
+use askama::Template;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use log::error;
+use serde::Deserialize;
+use sqlx::SqlitePool;
+
+use crate::{
+    csrf_token::CsrfToken,
+    mail::build_email_for_user,
+    queries,
+    reauth::{mark_authenticated, safe_next},
+    security_events::{record_security_event, SecurityEventKind},
+    session_values::ReauthCode,
+    templates::*,
+    SessionValue,
+};
+
+#[derive(Deserialize)]
+struct ReauthQuery {
+    next: Option<String>,
+}
+
+/// Ask the user to confirm it's them before a sensitive change
+#[get("/reauth")]
+async fn reauth(
+    identity: Identity,
+    session: Session,
+    query: web::Query<ReauthQuery>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    ReauthCode::remove(&session);
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let has_passkeys = sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM webauthn_credentials WHERE user_id = $1);",
+        user.id
+    )
+    .fetch_one(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+        != 0;
+
+    let csrf_token = CsrfToken::get_or_create(&session)?;
+    let body = ReauthPage {
+        title: "Confirm It's You . Silly Goals".into(),
+        next: safe_next(query.next.as_deref()),
+        has_passkeys,
+        code_sent: false,
+        error: None,
+        csrf_token,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+#[derive(Deserialize)]
+struct ReauthCodeForm {
+    next: Option<String>,
+    csrftoken: String,
+}
+
+/// Email the user a code to confirm it's them
+#[post("/reauth/code")]
+async fn send_reauth_code(
+    identity: Identity,
+    session: Session,
+    form: web::Form<ReauthCodeForm>,
+    pool: web::Data<SqlitePool>,
+    mailer: web::Data<AsyncSmtpTransport<Tokio1Executor>>,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let reauth_code = ReauthCode::new();
+    reauth_code.save(&session)?;
+
+    let message = build_email_for_user(
+        &user.email,
+        "Confirmation Code for Silly Goals",
+        &format!(
+            "Use code {reauth_code} to confirm it's you before changing your account. \
+            If you didn't ask for this, someone may be using a device you're logged in \
+            on. You can sign it out from your profile."
+        ),
+    )?;
+
+    tokio::spawn(async move {
+        match mailer.send(message).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!("Could not sent message: {}", e);
+            }
+        }
+    });
+
+    let csrf_token = CsrfToken::get_or_create(&session)?;
+    let body = ReauthPage {
+        title: "Confirm It's You . Silly Goals".into(),
+        next: safe_next(form.next.as_deref()),
+        has_passkeys: false,
+        code_sent: true,
+        error: None,
+        csrf_token,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+#[derive(Deserialize)]
+struct FinishReauthForm {
+    code: String,
+    next: Option<String>,
+    csrftoken: String,
+}
+
+/// Check the emailed code, then send the user back to what they were doing
+#[post("/reauth")]
+async fn finish_reauth(
+    req: HttpRequest,
+    identity: Identity,
+    session: Session,
+    form: web::Form<FinishReauthForm>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let next = safe_next(form.next.as_deref());
+
+    let Some(mut reauth_code) = ReauthCode::get(&session).map_err(ErrorInternalServerError)? else {
+        let query =
+            serde_html_form::to_string([("next", &next)]).map_err(ErrorInternalServerError)?;
+        return Ok(HttpResponse::SeeOther()
+            .insert_header(("Location", format!("/reauth?{query}")))
+            .finish());
+    };
+
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    if !reauth_code.verify(&form.code) {
+        record_security_event(
+            &mut conn,
+            &req,
+            user.id,
+            SecurityEventKind::CodeFailed,
+            Some("Confirming it's you"),
+        )
+        .await?;
+        let (code_sent, error) = if reauth_code.attempts_left() {
+            reauth_code.save(&session)?;
+            (true, "Invalid code")
+        } else {
+            ReauthCode::remove(&session);
+            (false, "Too many wrong codes, please get a new one")
+        };
+        let csrf_token = CsrfToken::get_or_create(&session)?;
+        let body = ReauthPage {
+            title: "Confirm It's You . Silly Goals".into(),
+            next,
+            has_passkeys: false,
+            code_sent,
+            error: Some(error.into()),
+            csrf_token,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?;
+        return Ok(HttpResponse::Ok().body(body));
+    }
+
+    ReauthCode::remove(&session);
+    mark_authenticated(&mut conn, &identity).await?;
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header(("Location", next))
+        .finish())
+}
@@ -0,0 +1,292 @@
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{error::ErrorInternalServerError, get, post, web, HttpRequest, HttpResponse};
+use askama::Template;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use log::{error, info};
+use serde::Deserialize;
+use sqlx::{pool::PoolConnection, Sqlite, SqlitePool};
+
+use crate::{
+    csrf_token::CsrfToken,
+    htmx::{hx_trigger_notification, NotificationVariant},
+    login_links::hash_token,
+    login_sessions::{self, LoginMethod},
+    mail::build_email_for_user,
+    queries,
+    reauth::require_recent_auth,
+    recovery_codes::{
+        new_recovery_codes, normalize_recovery_code, recovery_codes_left, NewRecoveryCodes,
+    },
+    security_events::{record_security_event, SecurityEventKind},
+    templates::*,
+    SessionValue, User,
+};
+
+/// Ask for a recovery code, for users who can't get into their email or use
+/// any of their passkeys
+#[get("/recover")]
+async fn recover(session: Session, identity: Option<Identity>) -> actix_web::Result<HttpResponse> {
+    if identity.is_some() {
+        return Ok(HttpResponse::SeeOther()
+            .insert_header(("Location", "/profile"))
+            .finish());
+    }
+    let csrf_token = CsrfToken::get_or_create(&session)?;
+    let body = RecoverPage {
+        title: "Recover Your Account . Silly Goals".into(),
+        csrf_token,
+        error: None,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(("X-Robots-Tag", "noindex, nofollow"))
+        .body(body))
+}
+
+#[derive(Deserialize)]
+struct RecoverForm {
+    code: String,
+    csrftoken: String,
+}
+
+/// Use up a recovery code and log in with it. Whoever had the lost devices
+/// shouldn't keep access, so the old passkeys are removed and every other
+/// device is logged out.
+#[post("/recover")]
+async fn post_recover(
+    req: HttpRequest,
+    session: Session,
+    form: web::Form<RecoverForm>,
+    pool: web::Data<SqlitePool>,
+    mailer: web::Data<AsyncSmtpTransport<Tokio1Executor>>,
+    hostname: web::Data<String>,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let code_hash = hash_token(&normalize_recovery_code(&form.code));
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    // Accounts waiting to be deleted can't log in, so their codes are left alone
+    let user_id = sqlx::query_scalar!(
+        r#"UPDATE recovery_codes SET used_at = CURRENT_TIMESTAMP
+        WHERE code_hash = $1 AND used_at IS NULL
+        AND user_id IN (SELECT id FROM users WHERE deletion_scheduled_for IS NULL)
+        RETURNING user_id as "user_id!";"#,
+        code_hash
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    let Some(user_id) = user_id else {
+        let csrf_token = CsrfToken::get_or_create(&session)?;
+        let body = RecoverPage {
+            title: "Recover Your Account . Silly Goals".into(),
+            csrf_token,
+            error: Some("That code isn't right, or it has already been used".into()),
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?;
+        return Ok(HttpResponse::Ok()
+            .insert_header(("X-Robots-Tag", "noindex, nofollow"))
+            .body(body));
+    };
+
+    let user = queries::get_user_by_id(&mut conn, user_id).await?;
+
+    sqlx::query!(
+        "DELETE FROM webauthn_credentials WHERE user_id = $1;",
+        user.id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+    sqlx::query!("DELETE FROM login_sessions WHERE user_id = $1;", user.id)
+        .execute(&mut conn)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    // The lost inbox may be in someone else's hands, and an undo link still
+    // waiting there would let them take the account back
+    sqlx::query!(
+        "UPDATE email_change_reverts SET used_at = CURRENT_TIMESTAMP
+        WHERE user_id = $1 AND used_at IS NULL;",
+        user.id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    login_sessions::login(
+        &mut conn,
+        &req,
+        &session,
+        user.userid,
+        LoginMethod::Recovery,
+    )
+    .await?;
+    record_security_event(
+        &mut conn,
+        &req,
+        user.id,
+        SecurityEventKind::RecoveryCodeUsed,
+        None,
+    )
+    .await?;
+    info!(
+        "User {} recovered their account with a recovery code",
+        user.id
+    );
+
+    // Likely to go unread, but if the inbox does come back it says what happened
+    let message = build_email_for_user(
+        &user.email,
+        "Your Silly Goals account was recovered",
+        &format!(
+            "A recovery code was just used to get into your Silly Goals account. \
+            Your passkeys were removed and every device was logged out.\n\n\
+            If this wasn't you, use another of your recovery codes to get back \
+            in, then change your email and get new codes: https://{}/recover",
+            hostname.as_str()
+        ),
+    )?;
+    tokio::spawn(async move {
+        match mailer.send(message).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!("Could not sent message: {}", e);
+            }
+        }
+    });
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header(("Location", "/recover/done"))
+        .finish())
+}
+
+/// What to do next after recovering an account: set an email that works and a
+/// passkey for this device
+#[get("/recover/done")]
+async fn account_recovered(
+    identity: Identity,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+    let groups = queries::get_group_links(&mut conn, user.id).await?;
+    let codes_left = recovery_codes_left(&mut conn, user.id).await?;
+
+    let body = AccountRecoveredPage {
+        title: "Account Recovered . Silly Goals".into(),
+        user,
+        groups,
+        codes_left,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+async fn render_recovery_codes(
+    conn: &mut PoolConnection<Sqlite>,
+    session: &Session,
+    user: &User,
+    new_codes: Option<Vec<String>>,
+) -> actix_web::Result<String> {
+    let codes_left = recovery_codes_left(conn, user.id).await?;
+    let has_passkeys = sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM webauthn_credentials WHERE user_id = $1);",
+        user.id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+        != 0;
+    let csrf_token = CsrfToken::get_or_create(session)?;
+    RecoveryCodesPartial {
+        new_codes,
+        codes_left,
+        has_passkeys,
+        csrf_token,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)
+}
+
+/// How many recovery codes the user has left, shown on the profile page. Codes
+/// made with the first passkey are shown here, once.
+#[get("/profile/recovery-codes")]
+async fn profile_recovery_codes(
+    identity: Identity,
+    session: Session,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let new_codes = NewRecoveryCodes::get(&session)
+        .map_err(ErrorInternalServerError)?
+        .map(|codes| codes.0);
+    NewRecoveryCodes::remove(&session);
+
+    let body = render_recovery_codes(&mut conn, &session, &user, new_codes).await?;
+    Ok(HttpResponse::Ok().body(body))
+}
+
+#[derive(Deserialize)]
+struct RecoveryCodesForm {
+    csrftoken: String,
+}
+
+/// Replace the user's recovery codes, so the old ones stop working
+#[post("/profile/recovery-codes")]
+async fn regenerate_recovery_codes(
+    req: HttpRequest,
+    identity: Identity,
+    session: Session,
+    form: web::Form<RecoveryCodesForm>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+    require_recent_auth(&mut conn, &req, &identity).await?;
+
+    let codes = new_recovery_codes(&mut conn, user.id).await?;
+    record_security_event(
+        &mut conn,
+        &req,
+        user.id,
+        SecurityEventKind::RecoveryCodesCreated,
+        None,
+    )
+    .await?;
+
+    let notification = hx_trigger_notification(
+        "New Recovery Codes".into(),
+        "Your old recovery codes no longer work".into(),
+        NotificationVariant::Success,
+        true,
+    );
+
+    let body = render_recovery_codes(&mut conn, &session, &user, Some(codes)).await?;
+    Ok(HttpResponse::Ok().append_header(notification).body(body))
+}
@@ -0,0 +1,43 @@
+use actix_identity::Identity;
+use actix_web::{error::ErrorInternalServerError, get, web, HttpResponse};
+use askama::Template;
+use sqlx::SqlitePool;
+
+use crate::{htmx::IsHtmx, queries, security_events::get_security_events, templates::*};
+
+/// The user's own security history: logins, wrong codes and account changes
+#[get("/profile/security")]
+async fn security_history(
+    identity: Identity,
+    pool: web::Data<SqlitePool>,
+    is_hx: IsHtmx,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+    let events = get_security_events(&mut conn, user.id).await?;
+
+    if *is_hx {
+        let body = SecurityEventsPartial { events }
+            .render()
+            .map_err(ErrorInternalServerError)?;
+        return Ok(HttpResponse::Ok()
+            .insert_header(("HX-Trigger-After-Swap", "updateLocation"))
+            .body(body));
+    }
+
+    let groups = queries::get_group_links(&mut conn, user.id).await?;
+    let body = SecurityEventsPage {
+        title: "Security History . Silly Goals".into(),
+        user,
+        groups,
+        events,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
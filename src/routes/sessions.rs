@@ -0,0 +1,133 @@
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{error::ErrorInternalServerError, get, post, web, HttpResponse};
+use askama::Template;
+use serde::Deserialize;
+use sqlx::{pool::PoolConnection, Sqlite, SqlitePool};
+
+use crate::{
+    csrf_token::CsrfToken,
+    htmx::{hx_trigger_notification, NotificationVariant},
+    login_sessions::LoginIdentity,
+    queries,
+    templates::*,
+    User,
+};
+
+async fn render_login_sessions(
+    conn: &mut PoolConnection<Sqlite>,
+    session: &Session,
+    user: &User,
+    login: LoginIdentity,
+) -> actix_web::Result<String> {
+    let sessions = queries::get_login_sessions(conn, user.id).await?;
+    let csrf_token = CsrfToken::get_or_create(session)?;
+    LoginSessionsPartial {
+        sessions,
+        current_session_id: login.session_id,
+        csrf_token,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)
+}
+
+/// Everywhere the user is logged in, shown on the profile page
+#[get("/profile/sessions")]
+async fn profile_sessions(
+    identity: Identity,
+    session: Session,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+    let login = LoginIdentity::from_identity(&identity)?;
+
+    let body = render_login_sessions(&mut conn, &session, &user, login).await?;
+    Ok(HttpResponse::Ok().body(body))
+}
+
+#[derive(Deserialize)]
+struct RevokeSessionForm {
+    csrftoken: String,
+}
+
+/// Log out one of the user's other devices. It's logged out on its next request.
+#[post("/profile/sessions/{id}/revoke")]
+async fn revoke_session(
+    identity: Identity,
+    session: Session,
+    path: web::Path<i64>,
+    form: web::Form<RevokeSessionForm>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let id = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+    let login = LoginIdentity::from_identity(&identity)?;
+
+    sqlx::query!(
+        "DELETE FROM login_sessions WHERE id = $1 AND user_id = $2 AND session_id != $3;",
+        id,
+        user.id,
+        login.session_id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    let notification = hx_trigger_notification(
+        "Signed Out".into(),
+        "That device has been logged out".into(),
+        NotificationVariant::Success,
+        true,
+    );
+
+    let body = render_login_sessions(&mut conn, &session, &user, login).await?;
+    Ok(HttpResponse::Ok().append_header(notification).body(body))
+}
+
+/// Log out everywhere except this device
+#[post("/profile/sessions/revoke-others")]
+async fn revoke_other_sessions(
+    identity: Identity,
+    session: Session,
+    form: web::Form<RevokeSessionForm>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+    let login = LoginIdentity::from_identity(&identity)?;
+
+    sqlx::query!(
+        "DELETE FROM login_sessions WHERE user_id = $1 AND session_id != $2;",
+        user.id,
+        login.session_id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    let notification = hx_trigger_notification(
+        "Signed Out Everywhere Else".into(),
+        "Your other devices have been logged out".into(),
+        NotificationVariant::Success,
+        true,
+    );
+
+    let body = render_login_sessions(&mut conn, &session, &user, login).await?;
+    Ok(HttpResponse::Ok().append_header(notification).body(body))
+}
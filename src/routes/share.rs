@@ -0,0 +1,201 @@
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{
+    delete,
+    error::{ErrorBadRequest, ErrorInternalServerError},
+    get, post, web, HttpResponse,
+};
+use askama::Template;
+use chrono::Utc;
+use serde::Deserialize;
+use sqlx::{pool::PoolConnection, Sqlite, SqlitePool};
+
+use crate::{
+    csrf_token::CsrfToken,
+    html_form::HtmlForm,
+    htmx::{hx_trigger_notification, NotificationVariant},
+    members::Permission,
+    queries,
+    routes::dashboard::group_goals_by_stage,
+    share::{new_share_token, EXPIRY_CHOICES},
+    templates::*,
+};
+
+async fn render_share_links(
+    conn: &mut PoolConnection<Sqlite>,
+    session: &Session,
+    hostname: &str,
+    group_id: i64,
+) -> actix_web::Result<String> {
+    let share_links = queries::get_share_links(conn, group_id).await?;
+    let csrf_token = CsrfToken::get_or_create(session)?;
+    ShareLinksPartial {
+        group_id,
+        share_links,
+        hostname: hostname.to_owned(),
+        now: Utc::now().naive_utc(),
+        csrf_token,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)
+}
+
+/// A read only view of a group for people without an account. The token in
+/// the url is the only authentication, so nothing about the members is shown.
+#[get("/share/{token}")]
+async fn shared_group(
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let token = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let shared = queries::get_shared_group(&mut conn, &token).await?;
+
+    let group = queries::get_group_with_info(&mut conn, shared.owner_id, shared.group_id).await?;
+    let goals = queries::get_goals_for_group(&mut conn, group.id).await?;
+    let goals_in_stages = group_goals_by_stage(&goals, group.goal_sort);
+
+    let body = SharedGroupPage {
+        title: format!("{} . Silly Goals", group.title),
+        group: group.into(),
+        goals_in_stages,
+        hide_descriptions: shared.hide_descriptions,
+    }
+    .render()
+    .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(("X-Robots-Tag", "noindex, nofollow"))
+        .insert_header(("Referrer-Policy", "no-referrer"))
+        .insert_header(("Cache-Control", "private, max-age=60"))
+        .body(body))
+}
+
+/// The share links for a group, shown on its edit page
+#[get("/groups/{id}/share-links")]
+async fn group_share_links(
+    identity: Identity,
+    session: Session,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+    hostname: web::Data<String>,
+) -> actix_web::Result<HttpResponse> {
+    let group_id = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let group = queries::get_group(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::ManageGroup)?;
+
+    let body = render_share_links(&mut conn, &session, hostname.as_str(), group.id).await?;
+    Ok(HttpResponse::Ok().body(body))
+}
+
+#[derive(Deserialize)]
+struct ShareLinkForm {
+    /// Left empty for a link that never expires
+    expires_in_days: Option<i64>,
+    #[serde(default)]
+    hide_descriptions: bool,
+    csrftoken: String,
+}
+
+#[post("/groups/{id}/share-links")]
+async fn post_share_link(
+    identity: Identity,
+    session: Session,
+    path: web::Path<i64>,
+    form: HtmlForm<ShareLinkForm>,
+    pool: web::Data<SqlitePool>,
+    hostname: web::Data<String>,
+) -> actix_web::Result<HttpResponse> {
+    CsrfToken::verify_from_session(&session, &form.csrftoken)?;
+    let group_id = path.into_inner();
+    if let Some(days) = form.expires_in_days {
+        if !EXPIRY_CHOICES.iter().any(|(choice, _)| *choice == days) {
+            return Err(ErrorBadRequest("Invalid expiry"));
+        }
+    }
+
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let group = queries::get_group(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::ManageGroup)?;
+
+    let token = new_share_token();
+    sqlx::query!(
+        "INSERT INTO share_links(group_id, token, hide_descriptions, expires_at)
+        VALUES ($1, $2, $3, datetime('now', '+' || $4 || ' days'));",
+        group.id,
+        token,
+        form.hide_descriptions,
+        form.expires_in_days
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    let notification = hx_trigger_notification(
+        "Share Link Created".into(),
+        "Anyone with the link can see this group, but not change it".into(),
+        NotificationVariant::Success,
+        true,
+    );
+
+    let body = render_share_links(&mut conn, &session, hostname.as_str(), group.id).await?;
+    Ok(HttpResponse::Ok().append_header(notification).body(body))
+}
+
+/// Turn off a share link for good
+#[delete("/groups/{id}/share-links/{link_id}")]
+async fn revoke_share_link(
+    identity: Identity,
+    session: Session,
+    path: web::Path<(i64, i64)>,
+    pool: web::Data<SqlitePool>,
+    hostname: web::Data<String>,
+) -> actix_web::Result<HttpResponse> {
+    let (group_id, link_id) = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let group = queries::get_group(&mut conn, user.id, group_id).await?;
+    group.role.require(Permission::ManageGroup)?;
+
+    sqlx::query!(
+        "DELETE FROM share_links WHERE id = $1 AND group_id = $2;",
+        link_id,
+        group.id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+
+    let notification = hx_trigger_notification(
+        "Share Link Turned Off".into(),
+        "The link no longer works".into(),
+        NotificationVariant::Success,
+        true,
+    );
+
+    let body = render_share_links(&mut conn, &session, hostname.as_str(), group.id).await?;
+    Ok(HttpResponse::Ok().append_header(notification).body(body))
+}
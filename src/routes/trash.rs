@@ -0,0 +1,162 @@
+use actix_identity::Identity;
+use actix_web::{
+    error::{ErrorInternalServerError, ErrorNotFound},
+    get, patch, web, HttpResponse,
+};
+use askama::Template;
+use serde::Deserialize;
+use sqlx::SqlitePool;
+
+use crate::{
+    html_form::HtmlForm,
+    htmx::{hx_trigger_notification, HxHeaderInfo, IsHtmx, NotificationVariant},
+    queries,
+    templates::*,
+    trash::TrashRetention,
+};
+
+/// Deleted goals and groups that can still be restored
+#[get("/trash")]
+async fn trash(
+    identity: Identity,
+    pool: web::Data<SqlitePool>,
+    retention: web::Data<TrashRetention>,
+    is_hx: IsHtmx,
+    hx_headers: HxHeaderInfo,
+) -> actix_web::Result<HttpResponse> {
+    let retention = *retention.get_ref();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let deleted_groups = queries::get_deleted_groups(&mut conn, user.id, retention).await?;
+    let deleted_goals = queries::get_deleted_goals(&mut conn, user.id, retention).await?;
+
+    let body = if *is_hx && !hx_headers.boosted {
+        TrashPartial {
+            deleted_groups,
+            deleted_goals,
+            retention_days: retention.0,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?
+    } else {
+        let groups = queries::get_group_links(&mut conn, user.id).await?;
+        TrashPage {
+            title: "Silly Goals".into(),
+            user,
+            groups,
+            deleted_groups,
+            deleted_goals,
+            retention_days: retention.0,
+        }
+        .render()
+        .map_err(ErrorInternalServerError)?
+    };
+    Ok(HttpResponse::Ok()
+        .insert_header(("HX-Trigger-After-Swap", "updateLocation"))
+        .body(body))
+}
+
+#[derive(Deserialize)]
+struct RestoreGoalsForm {
+    #[serde(default)]
+    goals: Vec<i64>,
+}
+
+/// Take goals back out of the trash, as long as their group isn't in there too
+#[patch("/trash/goals/restore")]
+async fn restore_goals(
+    identity: Identity,
+    form: HtmlForm<RestoreGoalsForm>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let ids = serde_json::to_string(&form.goals).map_err(ErrorInternalServerError)?;
+    let restored = sqlx::query!(
+        "UPDATE goals SET deleted_at = NULL
+        WHERE id IN (SELECT value FROM json_each($1))
+        AND deleted_at IS NOT NULL
+        AND group_id IN (
+            SELECT g.id FROM groups g
+            INNER JOIN group_members m
+            ON m.group_id = g.id
+            WHERE m.user_id = $2
+            AND m.role != 'viewer'
+            AND g.deleted_at IS NULL
+        );",
+        ids,
+        user.id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .rows_affected();
+
+    let message = if restored == 1 {
+        "Restored 1 goal".to_string()
+    } else {
+        format!("Restored {} goals", restored)
+    };
+    let notification = hx_trigger_notification(
+        "Goals Restored".into(),
+        message,
+        NotificationVariant::Success,
+        true,
+    );
+
+    Ok(HttpResponse::Ok()
+        .append_header(notification)
+        .insert_header(("HX-Trigger", "goalsChanged"))
+        .finish())
+}
+
+/// Take a group and its goals back out of the trash and go to it
+#[patch("/trash/groups/{id}/restore")]
+async fn restore_group(
+    identity: Identity,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let group_id = path.into_inner();
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let restored = sqlx::query!(
+        "UPDATE groups SET deleted_at = NULL
+        WHERE id = $1
+        AND deleted_at IS NOT NULL
+        AND id IN (
+            SELECT group_id FROM group_members
+            WHERE user_id = $2 AND role = 'owner'
+        );",
+        group_id,
+        user.id
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .rows_affected();
+
+    if restored == 0 {
+        return Err(ErrorNotFound("Group is not in the trash"));
+    }
+
+    // The sidebar has to be rebuilt, so load the whole group page
+    Ok(HttpResponse::Ok()
+        .insert_header(("HX-Redirect", format!("/groups/{}", group_id)))
+        .finish())
+}
@@ -4,14 +4,25 @@ use actix_web::{
     error::{ErrorBadRequest, ErrorInternalServerError},
     get, post,
     web::{self, Json},
-    HttpMessage, HttpRequest, HttpResponse,
+    HttpRequest, HttpResponse,
 };
+use base64::{engine::general_purpose, Engine};
+use lettre::{AsyncSmtpTransport, Tokio1Executor};
 use log::{debug, error};
 use serde::{Deserialize, Serialize};
 use sqlx::{types::Uuid, SqlitePool};
 use webauthn_rs::prelude::*;
 
-use crate::{queries, session_values::LoginEmail, SessionValue, WebauthnCredential};
+use crate::{
+    devices::{notify_new_device, remember_device, Device},
+    login_sessions::{self, LoginMethod},
+    queries,
+    reauth::mark_authenticated,
+    recovery_codes::{new_recovery_codes, NewRecoveryCodes},
+    security_events::{record_security_event, SecurityEventKind},
+    session_values::LoginEmail,
+    SavedPasskey, SessionValue, WebauthnCredential,
+};
 
 #[get("/webauthn/register")]
 async fn start_registration(
@@ -61,11 +72,23 @@ async fn start_registration(
 
     reg_state.save(&session)?;
 
+    // Passkeys need to be discoverable to log in without an email. webauthn-rs
+    // only has the older all-or-nothing flag, which would turn away security
+    // keys without storage, so ask for one with `residentKey` instead.
+    let mut ccr = serde_json::to_value(&ccr).map_err(ErrorInternalServerError)?;
+    if let Some(selection) = ccr
+        .pointer_mut("/publicKey/authenticatorSelection")
+        .and_then(|selection| selection.as_object_mut())
+    {
+        selection.insert("residentKey".into(), "preferred".into());
+    }
+
     Ok(HttpResponse::Ok().json(&ccr))
 }
 
 #[post("/webauthn/register")]
 async fn finish_registration(
+    req: HttpRequest,
     reg: Json<RegisterPublicKeyCredential>,
     identity: Identity,
     session: Session,
@@ -95,13 +118,16 @@ async fn finish_registration(
         ErrorInternalServerError(err)
     })?;
 
-    let credential_id = Uuid::new_v4();
+    let id = Uuid::new_v4();
+    // Encoded like the passkey json does, for finding the passkey in usernameless logins
+    let credential_id = general_purpose::URL_SAFE_NO_PAD.encode(sk.cred_id());
     sqlx::query!(
-        "INSERT INTO webauthn_credentials(id, user_id, passkey)
-        VALUES ($1, $2, $3);",
-        credential_id,
+        "INSERT INTO webauthn_credentials(id, user_id, passkey, credential_id, created_at)
+        VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP);",
+        id,
         user.id,
         sk_json,
+        credential_id,
     )
     .execute(&mut conn)
     .await
@@ -110,6 +136,43 @@ async fn finish_registration(
         ErrorInternalServerError(err)
     })?;
     PasskeyRegistration::remove(&session);
+    let short_id = SavedPasskey {
+        id,
+        credential_id: Some(credential_id),
+    }
+    .short_id();
+    record_security_event(
+        &mut conn,
+        &req,
+        user.id,
+        SecurityEventKind::PasskeyAdded,
+        Some(&short_id),
+    )
+    .await?;
+
+    // The first passkey comes with recovery codes, for if the user loses both
+    // it and their email
+    let has_recovery_codes = sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM recovery_codes WHERE user_id = $1);",
+        user.id
+    )
+    .fetch_one(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+        != 0;
+    if !has_recovery_codes {
+        let codes = new_recovery_codes(&mut conn, user.id).await?;
+        NewRecoveryCodes(codes).save(&session)?;
+        record_security_event(
+            &mut conn,
+            &req,
+            user.id,
+            SecurityEventKind::RecoveryCodesCreated,
+            None,
+        )
+        .await?;
+    }
+
     Ok(HttpResponse::Ok().finish())
 }
 
@@ -191,6 +254,9 @@ async fn finish_login(
     auth: Json<PublicKeyCredential>,
     session: Session,
     webauthn: web::Data<Webauthn>,
+    pool: web::Data<SqlitePool>,
+    mailer: web::Data<AsyncSmtpTransport<Tokio1Executor>>,
+    hostname: web::Data<String>,
 ) -> actix_web::Result<HttpResponse> {
     let auth_state = AuthState::get(&session).map_err(ErrorInternalServerError)?;
 
@@ -208,11 +274,212 @@ async fn finish_login(
         .finish_passkey_authentication(&auth, &auth_state.passkey_auth)
         .map_err(ErrorBadRequest)?;
 
-    Identity::login(&req.extensions(), auth_state.userid.to_string()).map_err(|err| {
-        error!("Could not login user, Error: {}", err);
-        ErrorInternalServerError(err)
-    })?;
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    login_sessions::login(
+        &mut conn,
+        &req,
+        &session,
+        auth_state.userid,
+        LoginMethod::Passkey,
+    )
+    .await?;
+
+    let user = queries::get_user_by_userid(&mut conn, auth_state.userid).await?;
+    let device = Device::from_request(&req);
+    if remember_device(&mut conn, user.id, &device).await? {
+        notify_new_device(mailer, &hostname, &user, &device, LoginMethod::Passkey)?;
+    }
 
     AuthState::remove(&session);
+    let mut response = HttpResponse::Ok();
+    if let Some(cookie) = device.new_cookie {
+        response.cookie(cookie);
+    }
+    Ok(response.finish())
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+struct DiscoverableAuthState {
+    discoverable_auth: DiscoverableAuthentication,
+}
+
+impl SessionValue for DiscoverableAuthState {
+    fn save_name() -> &'static str {
+        "discoverable_auth_state"
+    }
+}
+
+/// Start a passkey login without knowing who is logging in. The browser offers
+/// whichever passkeys it has for the site, either from a button or autofill.
+#[get("/webauthn/discoverable-login")]
+async fn start_discoverable_login(
+    session: Session,
+    webauthn: web::Data<Webauthn>,
+) -> actix_web::Result<HttpResponse> {
+    let (rcr, discoverable_auth) = webauthn
+        .start_discoverable_authentication()
+        .map_err(|err| {
+            error!("challenge authenticate {:?}", err);
+            ErrorInternalServerError(err)
+        })?;
+
+    DiscoverableAuthState::remove(&session);
+    DiscoverableAuthState { discoverable_auth }.save(&session)?;
+
+    Ok(HttpResponse::Ok().json(&rcr))
+}
+
+#[post("/webauthn/discoverable-login")]
+async fn finish_discoverable_login(
+    req: HttpRequest,
+    auth: Json<PublicKeyCredential>,
+    session: Session,
+    webauthn: web::Data<Webauthn>,
+    pool: web::Data<SqlitePool>,
+    mailer: web::Data<AsyncSmtpTransport<Tokio1Executor>>,
+    hostname: web::Data<String>,
+) -> actix_web::Result<HttpResponse> {
+    let Some(auth_state) =
+        DiscoverableAuthState::get(&session).map_err(ErrorInternalServerError)?
+    else {
+        return Ok(HttpResponse::SeeOther()
+            .insert_header(("Location", "/login"))
+            .finish());
+    };
+    DiscoverableAuthState::remove(&session);
+
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    // The credential id is the only thing that says who this is
+    let credential_id = general_purpose::URL_SAFE_NO_PAD.encode(auth.get_credential_id());
+    let credential = sqlx::query_as!(
+        WebauthnCredential,
+        r#"SELECT id as "id: Uuid", user_id, passkey
+        FROM webauthn_credentials
+        WHERE credential_id = $1;"#,
+        credential_id,
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?
+    .ok_or_else(|| ErrorBadRequest("Unknown passkey"))?;
+
+    let passkey: Passkey =
+        serde_json::from_str(&credential.passkey).map_err(ErrorInternalServerError)?;
+
+    webauthn
+        .finish_discoverable_authentication(
+            &auth,
+            auth_state.discoverable_auth,
+            &[DiscoverableKey::from(&passkey)],
+        )
+        .map_err(ErrorBadRequest)?;
+
+    let user = queries::get_user_by_id(&mut conn, credential.user_id).await?;
+
+    login_sessions::login(&mut conn, &req, &session, user.userid, LoginMethod::Passkey).await?;
+
+    let device = Device::from_request(&req);
+    if remember_device(&mut conn, user.id, &device).await? {
+        notify_new_device(mailer, &hostname, &user, &device, LoginMethod::Passkey)?;
+    }
+
+    let mut response = HttpResponse::Ok();
+    if let Some(cookie) = device.new_cookie {
+        response.cookie(cookie);
+    }
+    Ok(response.finish())
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+struct ReauthState {
+    passkey_auth: PasskeyAuthentication,
+}
+
+impl SessionValue for ReauthState {
+    fn save_name() -> &'static str {
+        "reauth_state"
+    }
+}
+
+/// Start confirming it's the logged in user with one of their passkeys
+#[get("/webauthn/reauth")]
+async fn start_reauth(
+    identity: Identity,
+    session: Session,
+    webauthn: web::Data<Webauthn>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let user = queries::get_user_from_identity(&mut conn, &identity).await?;
+
+    let allow_credential_items = sqlx::query_as!(
+        WebauthnCredential,
+        r#"SELECT id as "id: Uuid", user_id, passkey
+        FROM webauthn_credentials
+        WHERE user_id = $1;"#,
+        user.id,
+    )
+    .fetch_all(&mut conn)
+    .await
+    .map_err(ErrorInternalServerError)?;
+    let allowed_credentials: Vec<Passkey> = allow_credential_items
+        .iter()
+        .filter_map(|i| serde_json::from_str(&i.passkey).ok())
+        .collect();
+
+    let (rcr, passkey_auth) = webauthn
+        .start_passkey_authentication(&allowed_credentials)
+        .map_err(|err| {
+            error!("challenge authenticate {:?}", err);
+            ErrorBadRequest(err)
+        })?;
+
+    ReauthState::remove(&session);
+    ReauthState { passkey_auth }.save(&session)?;
+
+    Ok(HttpResponse::Ok().json(&rcr))
+}
+
+#[post("/webauthn/reauth")]
+async fn finish_reauth(
+    auth: Json<PublicKeyCredential>,
+    identity: Identity,
+    session: Session,
+    webauthn: web::Data<Webauthn>,
+    pool: web::Data<SqlitePool>,
+) -> actix_web::Result<HttpResponse> {
+    let Some(reauth_state) = ReauthState::get(&session).map_err(ErrorInternalServerError)? else {
+        return Err(ErrorBadRequest("No passkey confirmation was started"));
+    };
+    ReauthState::remove(&session);
+
+    // Only the user's own passkeys were allowed when this started
+    webauthn
+        .finish_passkey_authentication(&auth, &reauth_state.passkey_auth)
+        .map_err(ErrorBadRequest)?;
+
+    let mut conn = pool
+        .get_ref()
+        .acquire()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    queries::get_user_from_identity(&mut conn, &identity).await?;
+    mark_authenticated(&mut conn, &identity).await?;
+
     Ok(HttpResponse::Ok().finish())
 }
@@ -0,0 +1,143 @@
+use actix_web::{error::ErrorInternalServerError, HttpRequest};
+use chrono::NaiveDateTime;
+use log::error;
+use sqlx::{pool::PoolConnection, Sqlite};
+
+use crate::{client_ip::client_ip, login_sessions::summarize_user_agent};
+
+/// How many events the security history page shows
+pub const SHOWN_EVENTS: i64 = 100;
+
+/// Longer user agents are cut short, they're only kept to show the user
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// Something that happened to the security of an account
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq)]
+#[sqlx(rename_all = "snake_case")]
+pub enum SecurityEventKind {
+    Login,
+    CodeFailed,
+    PasskeyAdded,
+    PasskeyRemoved,
+    EmailChangeRequested,
+    EmailChangeConfirmed,
+    EmailChangeReverted,
+    DeletionRequested,
+    DeletionCancelled,
+    RecoveryCodeUsed,
+    RecoveryCodesCreated,
+    ApiTokenCreated,
+    ApiTokenRevoked,
+}
+
+impl SecurityEventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SecurityEventKind::Login => "Logged in",
+            SecurityEventKind::CodeFailed => "Wrong code entered",
+            SecurityEventKind::PasskeyAdded => "Passkey added",
+            SecurityEventKind::PasskeyRemoved => "Passkey removed",
+            SecurityEventKind::EmailChangeRequested => "Email change requested",
+            SecurityEventKind::EmailChangeConfirmed => "Email changed",
+            SecurityEventKind::EmailChangeReverted => "Email change undone",
+            SecurityEventKind::DeletionRequested => "Account deletion requested",
+            SecurityEventKind::DeletionCancelled => "Account deletion cancelled",
+            SecurityEventKind::RecoveryCodeUsed => "Recovered with a recovery code",
+            SecurityEventKind::RecoveryCodesCreated => "New recovery codes",
+            SecurityEventKind::ApiTokenCreated => "Token created",
+            SecurityEventKind::ApiTokenRevoked => "Token turned off",
+        }
+    }
+
+    /// Events the user should look twice at if they don't remember them
+    pub fn is_warning(&self) -> bool {
+        matches!(
+            self,
+            SecurityEventKind::CodeFailed
+                | SecurityEventKind::PasskeyRemoved
+                | SecurityEventKind::EmailChangeConfirmed
+                | SecurityEventKind::DeletionRequested
+                | SecurityEventKind::RecoveryCodeUsed
+        )
+    }
+}
+
+/// One entry in a user's security history
+#[derive(Clone, Debug)]
+pub struct SecurityEvent {
+    pub id: i64,
+    pub kind: SecurityEventKind,
+    pub detail: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl SecurityEvent {
+    pub fn device(&self) -> String {
+        self.user_agent
+            .as_deref()
+            .map(summarize_user_agent)
+            .unwrap_or_else(|| "Unknown device".into())
+    }
+}
+
+/// Add an event to the user's security history, along with where the request
+/// came from
+pub async fn record_security_event(
+    conn: &mut PoolConnection<Sqlite>,
+    req: &HttpRequest,
+    user_id: i64,
+    kind: SecurityEventKind,
+    detail: Option<&str>,
+) -> actix_web::Result<()> {
+    let ip = client_ip(req);
+    let user_agent = req
+        .headers()
+        .get("User-Agent")
+        .and_then(|agent| agent.to_str().ok())
+        .map(|agent| {
+            agent
+                .chars()
+                .take(MAX_USER_AGENT_LENGTH)
+                .collect::<String>()
+        });
+
+    sqlx::query!(
+        "INSERT INTO security_events(user_id, kind, detail, ip, user_agent)
+        VALUES ($1, $2, $3, $4, $5);",
+        user_id,
+        kind,
+        detail,
+        ip,
+        user_agent
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(|err| {
+        error!("Could not record security event: {}", err);
+        ErrorInternalServerError(err)
+    })?;
+    Ok(())
+}
+
+/// The user's most recent security events, newest first
+pub async fn get_security_events(
+    conn: &mut PoolConnection<Sqlite>,
+    user_id: i64,
+) -> actix_web::Result<Vec<SecurityEvent>> {
+    sqlx::query_as!(
+        SecurityEvent,
+        r#"SELECT id, kind as "kind: SecurityEventKind", detail, ip, user_agent,
+            created_at as "created_at: NaiveDateTime"
+        FROM security_events
+        WHERE user_id = $1
+        ORDER BY created_at DESC, id DESC
+        LIMIT $2;"#,
+        user_id,
+        SHOWN_EVENTS
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(ErrorInternalServerError)
+}
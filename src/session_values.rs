@@ -173,3 +173,49 @@ impl Not for UsedWebauthn {
         !self.0
     }
 }
+
+/// A code emailed to a logged in user to confirm it's them before a sensitive
+/// change. Guesses are counted, since the code is all that stands in the way.
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct ReauthCode {
+    code: LoginCode,
+    attempts: u8,
+}
+
+impl ReauthCode {
+    pub const MAX_ATTEMPTS: u8 = 5;
+
+    pub fn new() -> Self {
+        Self {
+            code: LoginCode::new(),
+            attempts: 0,
+        }
+    }
+
+    pub fn verify(&mut self, other: &str) -> bool {
+        self.attempts += 1;
+        self.code.verify(other)
+    }
+
+    pub fn attempts_left(&self) -> bool {
+        self.attempts < Self::MAX_ATTEMPTS
+    }
+}
+
+impl Default for ReauthCode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionValue for ReauthCode {
+    fn save_name() -> &'static str {
+        "reauth_code"
+    }
+}
+
+impl Display for ReauthCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code)
+    }
+}
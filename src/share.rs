@@ -0,0 +1,36 @@
+use base64::{engine::general_purpose, Engine};
+use chrono::NaiveDateTime;
+use rand::{thread_rng, Rng};
+
+/// How long a new share link can last, in days, and how to describe it
+pub const EXPIRY_CHOICES: [(i64, &str); 3] = [(1, "1 day"), (7, "1 week"), (30, "30 days")];
+
+/// A read only link to a group that anyone can open without logging in
+#[derive(Clone, Debug)]
+pub struct ShareLink {
+    pub id: i64,
+    pub token: String,
+    pub hide_descriptions: bool,
+    pub expires_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl ShareLink {
+    pub fn is_expired(&self, now: &NaiveDateTime) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= *now)
+    }
+}
+
+/// What a share link points to
+#[derive(Clone, Debug)]
+pub struct SharedGroup {
+    pub group_id: i64,
+    pub owner_id: i64,
+    pub hide_descriptions: bool,
+}
+
+pub fn new_share_token() -> String {
+    let mut token_bytes = [0u8; 32];
+    thread_rng().fill(&mut token_bytes);
+    general_purpose::URL_SAFE_NO_PAD.encode(token_bytes)
+}
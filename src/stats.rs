@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+
+use crate::{DeadlineType, Goal};
+
+/// Number of weeks shown in the completion history and cumulative flow chart
+const HISTORY_WEEKS: i64 = 8;
+
+const CHART_WIDTH: f64 = 640.0;
+const CHART_HEIGHT: f64 = 240.0;
+const CHART_PADDING_LEFT: f64 = 32.0;
+const CHART_PADDING_RIGHT: f64 = 8.0;
+const CHART_PADDING_TOP: f64 = 8.0;
+const CHART_PADDING_BOTTOM: f64 = 24.0;
+
+#[derive(Clone, Debug)]
+pub struct StageTransition {
+    pub goal_id: i64,
+    pub from_stage: Option<i64>,
+    pub to_stage: i64,
+    /// Recorded when stage tracking began, for goals that already existed
+    pub backfilled: bool,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug)]
+pub struct WeeklyCompletions {
+    pub week_start: NaiveDate,
+    pub count: usize,
+    /// Size of the bar relative to the busiest week
+    pub percent: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct StageDuration {
+    pub stage: String,
+    pub average: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct OverdueRate {
+    pub overdue: usize,
+    pub total: usize,
+}
+
+impl OverdueRate {
+    pub fn percent(&self) -> usize {
+        (self.overdue * 100).checked_div(self.total).unwrap_or(0)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FlowBand {
+    pub stage: String,
+    pub color: &'static str,
+    pub points: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct FlowChart {
+    pub width: f64,
+    pub height: f64,
+    pub plot_left: f64,
+    pub plot_right: f64,
+    pub plot_top: f64,
+    pub plot_bottom: f64,
+    pub bands: Vec<FlowBand>,
+    pub max_count: usize,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+#[derive(Clone, Debug)]
+pub struct GroupStats {
+    pub completed_per_week: Vec<WeeklyCompletions>,
+    pub stage_durations: Vec<StageDuration>,
+    /// Only calculated for groups with a tone that uses deadlines
+    pub overdue_rate: Option<OverdueRate>,
+    pub flow_chart: FlowChart,
+}
+
+impl GroupStats {
+    /// Build the stats for a group from its goals and their stage transitions.
+    /// Transitions must be ordered by goal and then by time.
+    pub fn build(
+        stages: &[String],
+        deadline: &DeadlineType,
+        goals: &[Goal],
+        transitions: &[StageTransition],
+        now: NaiveDateTime,
+    ) -> Self {
+        let final_stage = stages.len() as i64 - 1;
+        let mut by_goal: HashMap<i64, Vec<&StageTransition>> = HashMap::new();
+        for transition in transitions {
+            by_goal
+                .entry(transition.goal_id)
+                .or_default()
+                .push(transition);
+        }
+
+        Self {
+            completed_per_week: completed_per_week(transitions, final_stage, now.date()),
+            stage_durations: stage_durations(stages, &by_goal),
+            overdue_rate: overdue_rate(deadline, goals, &by_goal, final_stage, now.date()),
+            flow_chart: flow_chart(stages, &by_goal, now.date()),
+        }
+    }
+}
+
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+fn completed_per_week(
+    transitions: &[StageTransition],
+    final_stage: i64,
+    today: NaiveDate,
+) -> Vec<WeeklyCompletions> {
+    let this_week = week_start(today);
+    let mut weeks: Vec<WeeklyCompletions> = (0..HISTORY_WEEKS)
+        .rev()
+        .map(|i| WeeklyCompletions {
+            week_start: this_week - Duration::weeks(i),
+            count: 0,
+            percent: 0,
+        })
+        .collect();
+
+    // Only moves into the final stage count, not goals that started there or
+    // were already there when tracking began
+    let completions = transitions.iter().filter(|t| {
+        t.to_stage == final_stage && t.from_stage.is_some() && t.from_stage != Some(final_stage)
+    });
+    for transition in completions {
+        let week = week_start(transition.created_at.date());
+        if let Some(w) = weeks.iter_mut().find(|w| w.week_start == week) {
+            w.count += 1;
+        }
+    }
+
+    let busiest = weeks.iter().map(|w| w.count).max().unwrap_or(0).max(1);
+    for week in weeks.iter_mut() {
+        week.percent = week.count * 100 / busiest;
+    }
+    weeks
+}
+
+fn format_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes();
+    if minutes < 60 {
+        format!("{}m", minutes)
+    } else if minutes < 60 * 24 {
+        format!("{}h {}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{}d {}h", minutes / (60 * 24), (minutes % (60 * 24)) / 60)
+    }
+}
+
+/// Average time spent in each stage, only counting stays that have ended
+fn stage_durations(
+    stages: &[String],
+    by_goal: &HashMap<i64, Vec<&StageTransition>>,
+) -> Vec<StageDuration> {
+    let mut totals = vec![(Duration::zero(), 0i32); stages.len()];
+
+    for goal_transitions in by_goal.values() {
+        for pair in goal_transitions.windows(2) {
+            // When a goal reached its stage before tracking began isn't known
+            if pair[0].backfilled {
+                continue;
+            }
+            let stage = pair[0].to_stage;
+            if stage < 0 || stage as usize >= stages.len() {
+                continue;
+            }
+            let (total, count) = &mut totals[stage as usize];
+            *total = *total + (pair[1].created_at - pair[0].created_at);
+            *count += 1;
+        }
+    }
+
+    stages
+        .iter()
+        .zip(totals)
+        .map(|(stage, (total, count))| StageDuration {
+            stage: stage.clone(),
+            average: (count > 0).then(|| format_duration(total / count)),
+        })
+        .collect()
+}
+
+fn overdue_rate(
+    deadline: &DeadlineType,
+    goals: &[Goal],
+    by_goal: &HashMap<i64, Vec<&StageTransition>>,
+    final_stage: i64,
+    today: NaiveDate,
+) -> Option<OverdueRate> {
+    if *deadline == DeadlineType::Off {
+        return None;
+    }
+
+    let mut rate = OverdueRate {
+        overdue: 0,
+        total: 0,
+    };
+    for goal in goals {
+        let Some(goal_deadline) = goal
+            .deadline
+            .as_ref()
+            .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+        else {
+            continue;
+        };
+        rate.total += 1;
+
+        let overdue = if goal.stage >= final_stage {
+            by_goal
+                .get(&goal.id)
+                .and_then(|t| t.iter().rev().find(|t| t.to_stage == final_stage))
+                .map(|t| t.created_at.date() > goal_deadline)
+                .unwrap_or(false)
+        } else {
+            goal_deadline < today
+        };
+        if overdue {
+            rate.overdue += 1;
+        }
+    }
+    Some(rate)
+}
+
+fn stage_hex_color(stage: usize) -> &'static str {
+    match stage {
+        0 => "#f43f5e",
+        1 => "#f59e0b",
+        2 => "#0ea5e9",
+        3 => "#10b981",
+        _ => "#6b7280",
+    }
+}
+
+/// Cumulative flow of goals through the stages, sampled at the end of each day
+fn flow_chart(
+    stages: &[String],
+    by_goal: &HashMap<i64, Vec<&StageTransition>>,
+    today: NaiveDate,
+) -> FlowChart {
+    let days = HISTORY_WEEKS * 7;
+    let start = today - Duration::days(days - 1);
+
+    let counts: Vec<Vec<usize>> = (0..days)
+        .map(|i| {
+            let end_of_day = (start + Duration::days(i + 1)).and_hms_opt(0, 0, 0);
+            let mut day_counts = vec![0; stages.len()];
+            for goal_transitions in by_goal.values() {
+                let current = goal_transitions
+                    .iter()
+                    .take_while(|t| end_of_day.map(|e| t.created_at < e).unwrap_or(true))
+                    .last();
+                if let Some(stage) = current.and_then(|t| usize::try_from(t.to_stage).ok()) {
+                    if stage < day_counts.len() {
+                        day_counts[stage] += 1;
+                    }
+                }
+            }
+            day_counts
+        })
+        .collect();
+
+    let max_count = counts
+        .iter()
+        .map(|c| c.iter().sum::<usize>())
+        .max()
+        .unwrap_or(0);
+
+    let plot_left = CHART_PADDING_LEFT;
+    let plot_right = CHART_WIDTH - CHART_PADDING_RIGHT;
+    let plot_top = CHART_PADDING_TOP;
+    let plot_bottom = CHART_HEIGHT - CHART_PADDING_BOTTOM;
+    let x = |i: usize| plot_left + (plot_right - plot_left) * i as f64 / (days - 1) as f64;
+    let y = |v: usize| plot_bottom - (plot_bottom - plot_top) * v as f64 / max_count.max(1) as f64;
+
+    // Finished goals sit at the bottom of the chart, the first stage on top
+    let mut below = vec![0; counts.len()];
+    let mut bands = vec![];
+    for stage in (0..stages.len()).rev() {
+        let above: Vec<usize> = below
+            .iter()
+            .zip(counts.iter())
+            .map(|(b, c)| b + c[stage])
+            .collect();
+
+        let upper = above.iter().enumerate().map(|(i, v)| (x(i), y(*v)));
+        let lower = below.iter().enumerate().rev().map(|(i, v)| (x(i), y(*v)));
+        let points = upper
+            .chain(lower)
+            .map(|(px, py)| format!("{:.1},{:.1}", px, py))
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        bands.push(FlowBand {
+            stage: stages[stage].clone(),
+            color: stage_hex_color(stage),
+            points,
+        });
+        below = above;
+    }
+
+    FlowChart {
+        width: CHART_WIDTH,
+        height: CHART_HEIGHT,
+        plot_left,
+        plot_right,
+        plot_top,
+        plot_bottom,
+        bands,
+        max_count,
+        start,
+        end: today,
+    }
+}
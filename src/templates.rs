@@ -1,7 +1,17 @@
 use askama::Template;
 
 use crate::{
-    csrf_token::CsrfToken, DeadlineType, Goal, Group, GroupDisplay, GroupLink, Tone, User,
+    calendar::{Calendar, CalendarView},
+    csrf_token::CsrfToken,
+    login_sessions::LoginSession,
+    members::{GroupInvitation, GroupMember, GroupRole, PendingInvitation},
+    security_events::SecurityEvent,
+    share::{ShareLink, EXPIRY_CHOICES},
+    stats::GroupStats,
+    trash::{DeletedGoal, DeletedGroup},
+    ArchivedGroup, DeadlineType, Goal, GoalSearchResult, GoalSort, GoalWithGroup, Group,
+    GroupDisplay, GroupIcon, GroupLink, GroupSearchResult, Label, LabelColor, Priority,
+    SavedPasskey, Tone, User,
 };
 
 mod filters {
@@ -75,14 +85,142 @@ mod filters {
         }
     }
 
-    pub fn icon_from_word<S: ToString>(s: S) -> ::askama::Result<String> {
-        if let Some(c) = s.to_string().chars().next() {
-            Ok(format!("{}", c).to_uppercase())
-        } else {
-            Err(askama::Error::Custom(
-                anyhow!("Does not support empty string").into(),
-            ))
+    pub fn label_color(color: &crate::LabelColor) -> ::askama::Result<&'static str> {
+        use crate::LabelColor;
+        Ok(match color {
+            LabelColor::Gray => "bg-gray-100 text-gray-800 ring-gray-300",
+            LabelColor::Red => "bg-red-100 text-red-800 ring-red-300",
+            LabelColor::Orange => "bg-orange-100 text-orange-800 ring-orange-300",
+            LabelColor::Amber => "bg-amber-100 text-amber-800 ring-amber-300",
+            LabelColor::Green => "bg-green-100 text-green-800 ring-green-300",
+            LabelColor::Teal => "bg-teal-100 text-teal-800 ring-teal-300",
+            LabelColor::Sky => "bg-sky-100 text-sky-800 ring-sky-300",
+            LabelColor::Blue => "bg-blue-100 text-blue-800 ring-blue-300",
+            LabelColor::Violet => "bg-violet-100 text-violet-800 ring-violet-300",
+            LabelColor::Pink => "bg-pink-100 text-pink-800 ring-pink-300",
+        })
+    }
+
+    /// Every label used by the goals on a kanban board, for filtering
+    pub fn labels_in_use(
+        goals_in_stages: &[Vec<crate::Goal>],
+    ) -> ::askama::Result<Vec<crate::Label>> {
+        let mut labels: Vec<crate::Label> = vec![];
+        for goal in goals_in_stages.iter().flatten() {
+            for label in goal.labels.iter() {
+                if !labels.iter().any(|l| l.id == label.id) {
+                    labels.push(label.clone());
+                }
+            }
         }
+        labels.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(labels)
+    }
+
+    /// Whether an optional id from a filter select is `id`
+    pub fn is_selected(selected: &Option<i64>, id: &i64) -> ::askama::Result<bool> {
+        Ok(*selected == Some(*id))
+    }
+
+    /// Badge colors for a goal priority
+    pub fn priority_color(priority: &crate::Priority) -> ::askama::Result<&'static str> {
+        Ok(match priority {
+            crate::Priority::Low => "bg-zinc-50 text-zinc-600 ring-zinc-500/20",
+            crate::Priority::Normal => "bg-sky-50 text-sky-700 ring-sky-600/20",
+            crate::Priority::High => "bg-amber-50 text-amber-800 ring-amber-600/20",
+            crate::Priority::Urgent => "bg-rose-50 text-rose-700 ring-rose-600/20",
+        })
+    }
+
+    /// Label ids as a javascript array for alpine
+    pub fn label_ids(labels: &[crate::Label]) -> ::askama::Result<String> {
+        let ids = labels
+            .iter()
+            .map(|l| l.id.to_string())
+            .collect::<Vec<String>>()
+            .join(",");
+        Ok(format!("[{}]", ids))
+    }
+
+    /// Escape a search result and turn its match markers into `<mark>` tags
+    pub fn search_highlight<S: std::fmt::Display>(s: S) -> ::askama::Result<String> {
+        let escaped = askama::filters::escape(askama::Html, s)?.to_string();
+        Ok(escaped
+            .replace(
+                crate::SEARCH_MATCH_START,
+                "<mark class=\"bg-amber-200 rounded-sm\">",
+            )
+            .replace(crate::SEARCH_MATCH_END, "</mark>"))
+    }
+
+    /// Render a markdown description as sanitized HTML
+    pub fn render_markdown<S: std::fmt::Display>(s: S) -> ::askama::Result<String> {
+        Ok(crate::markdown::render(&s.to_string()))
+    }
+
+    /// The text of a markdown description without any formatting
+    pub fn markdown_text<S: std::fmt::Display>(s: S) -> ::askama::Result<String> {
+        Ok(crate::markdown::plain_text(&s.to_string()))
+    }
+
+    /// The inside of a group's badge: its built-in icon, its emoji, or the
+    /// first letter of the title when there's no icon
+    pub fn group_icon<S: std::fmt::Display>(
+        icon: &Option<String>,
+        title: S,
+    ) -> ::askama::Result<String> {
+        match icon.as_deref().map(|i| (i, crate::GroupIcon::from_name(i))) {
+            Some((_, Some(builtin))) => Ok(format!(
+                r#"<svg viewBox="0 0 24 24" fill="currentColor" class="h-3/4 w-3/4" aria-hidden="true">{}</svg>"#,
+                builtin.shape()
+            )),
+            Some((emoji, None)) => Ok(askama::filters::escape(askama::Html, emoji)?.to_string()),
+            None => {
+                let title = title.to_string();
+                let Some(c) = title.chars().next() else {
+                    return Err(askama::Error::Custom(
+                        anyhow!("Does not support empty string").into(),
+                    ));
+                };
+                let initial = c.to_uppercase().to_string();
+                Ok(askama::filters::escape(askama::Html, initial)?.to_string())
+            }
+        }
+    }
+
+    /// Classes for a group's badge in its accent colour, or `fallback` when it
+    /// doesn't have one
+    pub fn group_accent<S: std::fmt::Display>(
+        color: &Option<crate::LabelColor>,
+        fallback: S,
+    ) -> ::askama::Result<String> {
+        match color {
+            Some(color) => Ok(accent_color(color)?.to_string()),
+            None => Ok(fallback.to_string()),
+        }
+    }
+
+    pub fn accent_color(color: &crate::LabelColor) -> ::askama::Result<&'static str> {
+        use crate::LabelColor;
+        Ok(match color {
+            LabelColor::Gray => "border-gray-600 bg-gray-500 text-white",
+            LabelColor::Red => "border-red-600 bg-red-500 text-white",
+            LabelColor::Orange => "border-orange-600 bg-orange-500 text-white",
+            LabelColor::Amber => "border-amber-600 bg-amber-500 text-white",
+            LabelColor::Green => "border-green-600 bg-green-500 text-white",
+            LabelColor::Teal => "border-teal-600 bg-teal-500 text-white",
+            LabelColor::Sky => "border-sky-600 bg-sky-500 text-white",
+            LabelColor::Blue => "border-blue-600 bg-blue-500 text-white",
+            LabelColor::Violet => "border-violet-600 bg-violet-500 text-white",
+            LabelColor::Pink => "border-pink-600 bg-pink-500 text-white",
+        })
+    }
+
+    pub fn is_color(
+        color: &Option<crate::LabelColor>,
+        other: &crate::LabelColor,
+    ) -> ::askama::Result<bool> {
+        Ok(color.as_ref() == Some(other))
     }
 }
 
@@ -92,6 +230,7 @@ pub struct DashboardPage {
     pub title: String,
     pub user: User,
     pub groups: Vec<Group>,
+    pub invitations: Vec<PendingInvitation>,
 }
 
 #[derive(Template)]
@@ -99,6 +238,220 @@ pub struct DashboardPage {
 pub struct DashboardPartial {
     pub groups: Vec<Group>,
     pub user: User,
+    pub invitations: Vec<PendingInvitation>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/today.html")]
+pub struct TodayPage {
+    pub title: String,
+    pub user: User,
+    pub groups: Vec<GroupLink>,
+    pub overdue: Vec<GoalWithGroup>,
+    pub due_today: Vec<GoalWithGroup>,
+    pub due_this_week: Vec<GoalWithGroup>,
+    pub labels: Vec<Label>,
+    pub selected_label: Option<i64>,
+}
+
+#[derive(Template)]
+#[template(path = "partials/today.html")]
+pub struct TodayPartial {
+    pub overdue: Vec<GoalWithGroup>,
+    pub due_today: Vec<GoalWithGroup>,
+    pub due_this_week: Vec<GoalWithGroup>,
+    pub labels: Vec<Label>,
+    pub selected_label: Option<i64>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/calendar.html")]
+pub struct CalendarPage {
+    pub title: String,
+    pub user: User,
+    pub groups: Vec<GroupLink>,
+    pub calendar: Calendar,
+    pub labels: Vec<Label>,
+}
+
+#[derive(Template)]
+#[template(path = "partials/calendar.html")]
+pub struct CalendarPartial {
+    pub groups: Vec<GroupLink>,
+    pub calendar: Calendar,
+    pub labels: Vec<Label>,
+}
+
+#[derive(Template)]
+#[template(path = "partials/calendar_feed.html")]
+pub struct CalendarFeedPartial {
+    pub feed_url: Option<String>,
+    pub csrf_token: CsrfToken,
+}
+
+#[derive(Template)]
+#[template(path = "partials/login_sessions.html")]
+pub struct LoginSessionsPartial {
+    pub sessions: Vec<LoginSession>,
+    pub current_session_id: String,
+    pub csrf_token: CsrfToken,
+}
+
+#[derive(Template)]
+#[template(path = "partials/passkeys.html")]
+pub struct PasskeysPartial {
+    pub passkeys: Vec<SavedPasskey>,
+    pub csrf_token: CsrfToken,
+}
+
+#[derive(Template)]
+#[template(path = "partials/recovery_codes.html")]
+pub struct RecoveryCodesPartial {
+    pub new_codes: Option<Vec<String>>,
+    pub codes_left: i64,
+    pub has_passkeys: bool,
+    pub csrf_token: CsrfToken,
+}
+
+#[derive(Template)]
+#[template(path = "partials/share_links.html")]
+pub struct ShareLinksPartial {
+    pub group_id: i64,
+    pub share_links: Vec<ShareLink>,
+    pub hostname: String,
+    pub now: chrono::NaiveDateTime,
+    pub csrf_token: CsrfToken,
+}
+
+#[derive(Template)]
+#[template(path = "shared_group.html")]
+pub struct SharedGroupPage {
+    pub title: String,
+    pub group: GroupDisplay,
+    pub goals_in_stages: Vec<Vec<Goal>>,
+    pub hide_descriptions: bool,
+}
+
+#[derive(Template)]
+#[template(path = "partials/search_results.html")]
+pub struct SearchResultsPartial {
+    pub query: String,
+    pub goals: Vec<GoalSearchResult>,
+    pub groups: Vec<GroupSearchResult>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/labels.html")]
+pub struct LabelsPage {
+    pub title: String,
+    pub user: User,
+    pub groups: Vec<GroupLink>,
+    pub labels: Vec<Label>,
+    pub csrf_token: CsrfToken,
+}
+
+#[derive(Template)]
+#[template(path = "partials/labels.html")]
+pub struct LabelsPartial {
+    pub labels: Vec<Label>,
+    pub csrf_token: CsrfToken,
+}
+
+#[derive(Template)]
+#[template(path = "pages/trash.html")]
+pub struct TrashPage {
+    pub title: String,
+    pub user: User,
+    pub groups: Vec<GroupLink>,
+    pub deleted_groups: Vec<DeletedGroup>,
+    pub deleted_goals: Vec<DeletedGoal>,
+    pub retention_days: i64,
+}
+
+#[derive(Template)]
+#[template(path = "partials/trash.html")]
+pub struct TrashPartial {
+    pub deleted_groups: Vec<DeletedGroup>,
+    pub deleted_goals: Vec<DeletedGoal>,
+    pub retention_days: i64,
+}
+
+#[derive(Template)]
+#[template(path = "pages/archive.html")]
+pub struct ArchivePage {
+    pub title: String,
+    pub user: User,
+    pub groups: Vec<GroupLink>,
+    pub archived_groups: Vec<ArchivedGroup>,
+}
+
+#[derive(Template)]
+#[template(path = "partials/archive.html")]
+pub struct ArchivePartial {
+    pub archived_groups: Vec<ArchivedGroup>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/archived_group.html")]
+pub struct ArchivedGroupPage {
+    pub title: String,
+    pub user: User,
+    pub groups: Vec<GroupLink>,
+    pub group: GroupDisplay,
+    pub goals: Vec<Goal>,
+}
+
+#[derive(Template)]
+#[template(path = "partials/archived_group.html")]
+pub struct ArchivedGroupPartial {
+    pub group: GroupDisplay,
+    pub goals: Vec<Goal>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/group_members.html")]
+pub struct GroupMembersPage {
+    pub title: String,
+    pub user: User,
+    pub groups: Vec<GroupLink>,
+    pub group: Group,
+    pub members: Vec<GroupMember>,
+    pub invitations: Vec<GroupInvitation>,
+    pub csrf_token: CsrfToken,
+    pub user_id: i64,
+}
+
+#[derive(Template)]
+#[template(path = "partials/group_members.html")]
+pub struct GroupMembersPartial {
+    pub group: Group,
+    pub members: Vec<GroupMember>,
+    pub invitations: Vec<GroupInvitation>,
+    pub csrf_token: CsrfToken,
+    pub user_id: i64,
+}
+
+#[derive(Template)]
+#[template(path = "pages/invitation.html")]
+pub struct InvitationPage {
+    pub title: String,
+    pub user: User,
+    pub groups: Vec<GroupLink>,
+    pub invitation: PendingInvitation,
+    pub csrf_token: CsrfToken,
+}
+
+#[derive(Template)]
+#[template(path = "partials/invitation.html")]
+pub struct InvitationPartial {
+    pub invitation: PendingInvitation,
+    pub csrf_token: CsrfToken,
+}
+
+#[derive(Template)]
+#[template(path = "partials/group_links.html")]
+pub struct GroupLinksPartial {
+    pub groups: Vec<GroupLink>,
 }
 
 #[derive(Template)]
@@ -109,6 +462,7 @@ pub struct NewGroupPage {
     pub tones: Vec<Tone>,
     pub groups: Vec<Group>,
     pub csrf_token: CsrfToken,
+    pub invitations: Vec<PendingInvitation>,
 }
 
 #[derive(Template)]
@@ -127,6 +481,7 @@ pub struct DashboardEditGroupPage {
     pub groups: Vec<Group>,
     pub tones: Vec<Tone>,
     pub csrf_token: CsrfToken,
+    pub invitations: Vec<PendingInvitation>,
 }
 
 #[derive(Template)]
@@ -155,6 +510,23 @@ pub struct ShowGroupPartial {
     pub goals_in_stages: Vec<Vec<Goal>>,
 }
 
+#[derive(Template)]
+#[template(path = "pages/group_stats.html")]
+pub struct GroupStatsPage {
+    pub title: String,
+    pub user: User,
+    pub group: GroupDisplay,
+    pub stats: GroupStats,
+    pub groups: Vec<GroupLink>,
+}
+
+#[derive(Template)]
+#[template(path = "partials/group_stats.html")]
+pub struct GroupStatsPartial {
+    pub group: GroupDisplay,
+    pub stats: GroupStats,
+}
+
 #[derive(Template)]
 #[template(path = "pages/new_goal.html")]
 pub struct NewGoalPage {
@@ -165,6 +537,7 @@ pub struct NewGoalPage {
     pub selected_stage: usize,
     pub csrf_token: CsrfToken,
     pub groups: Vec<GroupLink>,
+    pub labels: Vec<Label>,
 }
 
 #[derive(Template)]
@@ -173,6 +546,7 @@ pub struct NewGoalPartial {
     pub group: GroupDisplay,
     pub selected_stage: usize,
     pub csrf_token: CsrfToken,
+    pub labels: Vec<Label>,
 }
 
 #[derive(Template)]
@@ -203,6 +577,7 @@ pub struct EditGoalPage {
     pub csrf_token: CsrfToken,
     pub goal: Goal,
     pub groups: Vec<GroupLink>,
+    pub labels: Vec<Label>,
 }
 
 #[derive(Template)]
@@ -211,6 +586,56 @@ pub struct EditGoalPartial {
     pub group: GroupDisplay,
     pub csrf_token: CsrfToken,
     pub goal: Goal,
+    pub labels: Vec<Label>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/move_goal.html")]
+pub struct MoveGoalPage {
+    pub title: String,
+    pub user: User,
+    pub group: GroupDisplay,
+    pub goals_in_stages: Vec<Vec<Goal>>,
+    pub csrf_token: CsrfToken,
+    pub goal: Goal,
+    pub groups: Vec<GroupLink>,
+    pub targets: Vec<GroupLink>,
+}
+
+#[derive(Template)]
+#[template(path = "partials/move_goal.html")]
+pub struct MoveGoalPartial {
+    pub group: GroupDisplay,
+    pub csrf_token: CsrfToken,
+    pub goal: Goal,
+    pub targets: Vec<GroupLink>,
+}
+
+#[derive(Template)]
+#[template(path = "partials/bulk_actions.html")]
+pub struct BulkActionsPartial {
+    /// Set when the goals all come from one group, so stages can be named
+    pub group: Option<GroupDisplay>,
+    pub groups: Vec<GroupLink>,
+    pub archived: bool,
+    pub csrf_token: CsrfToken,
+}
+
+#[derive(Template)]
+#[template(path = "pages/archived_goals.html")]
+pub struct ArchivedGoalsPage {
+    pub title: String,
+    pub user: User,
+    pub groups: Vec<GroupLink>,
+    pub group: GroupDisplay,
+    pub goals: Vec<Goal>,
+}
+
+#[derive(Template)]
+#[template(path = "partials/archived_goals.html")]
+pub struct ArchivedGoalsPartial {
+    pub group: GroupDisplay,
+    pub goals: Vec<Goal>,
 }
 
 #[derive(Template)]
@@ -239,6 +664,9 @@ pub struct GroupEditGroupPage {
 pub struct RegisterStart {
     pub title: String,
     pub csrf_token: CsrfToken,
+    pub pow_challenge: Option<String>,
+    pub pow_bits: u32,
+    pub wait_minutes: Option<i64>,
 }
 
 #[derive(Template)]
@@ -254,6 +682,9 @@ pub struct RegisterFinish {
 pub struct LoginStart {
     pub title: String,
     pub csrf_token: CsrfToken,
+    pub pow_challenge: Option<String>,
+    pub pow_bits: u32,
+    pub wait_minutes: Option<i64>,
 }
 
 #[derive(Template)]
@@ -262,6 +693,82 @@ pub struct LoginSelect {
     pub title: String,
 }
 
+#[derive(Template)]
+#[template(path = "login_link.html")]
+pub struct LoginLinkPage {
+    pub title: String,
+    pub token: String,
+    pub valid: bool,
+    pub csrf_token: CsrfToken,
+}
+
+#[derive(Template)]
+#[template(path = "email_change_revert.html")]
+pub struct EmailChangeRevertPage {
+    pub title: String,
+    pub token: String,
+    pub valid: bool,
+    pub old_email: Option<String>,
+    pub reverted: bool,
+    pub removed_passkeys: u64,
+    pub removed_recovery_codes: bool,
+    pub error: Option<String>,
+    pub csrf_token: CsrfToken,
+}
+
+#[derive(Template)]
+#[template(path = "restore_account.html")]
+pub struct RestoreAccountPage {
+    pub title: String,
+    pub token: String,
+    pub valid: bool,
+    pub restored: bool,
+    pub csrf_token: CsrfToken,
+}
+
+#[derive(Template)]
+#[template(path = "pages/security_events.html")]
+pub struct SecurityEventsPage {
+    pub title: String,
+    pub user: User,
+    pub groups: Vec<GroupLink>,
+    pub events: Vec<SecurityEvent>,
+}
+
+#[derive(Template)]
+#[template(path = "partials/security_events.html")]
+pub struct SecurityEventsPartial {
+    pub events: Vec<SecurityEvent>,
+}
+
+#[derive(Template)]
+#[template(path = "recover.html")]
+pub struct RecoverPage {
+    pub title: String,
+    pub csrf_token: CsrfToken,
+    pub error: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/account_recovered.html")]
+pub struct AccountRecoveredPage {
+    pub title: String,
+    pub user: User,
+    pub groups: Vec<GroupLink>,
+    pub codes_left: i64,
+}
+
+#[derive(Template)]
+#[template(path = "reauth.html")]
+pub struct ReauthPage {
+    pub title: String,
+    pub next: String,
+    pub has_passkeys: bool,
+    pub code_sent: bool,
+    pub error: Option<String>,
+    pub csrf_token: CsrfToken,
+}
+
 #[derive(Template)]
 #[template(path = "login_finish.html")]
 pub struct LoginFinish {
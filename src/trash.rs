@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use log::{error, info};
+use sqlx::SqlitePool;
+
+use crate::queries;
+
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+/// How many days deleted goals and groups stay in the trash before they are
+/// removed for good. Set with `TRASH_RETENTION_DAYS`.
+#[derive(Clone, Copy, Debug)]
+pub struct TrashRetention(pub i64);
+
+impl TrashRetention {
+    pub fn from_env() -> Self {
+        let days = dotenvy::var("TRASH_RETENTION_DAYS")
+            .ok()
+            .and_then(|days| days.parse().ok())
+            .filter(|days| *days > 0)
+            .unwrap_or(DEFAULT_RETENTION_DAYS);
+        Self(days)
+    }
+}
+
+impl Default for TrashRetention {
+    fn default() -> Self {
+        Self(DEFAULT_RETENTION_DAYS)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DeletedGroup {
+    pub id: i64,
+    pub title: String,
+    pub goal_count: i64,
+    pub deleted_at: NaiveDateTime,
+    pub purge_on: NaiveDate,
+}
+
+#[derive(Clone, Debug)]
+pub struct DeletedGoal {
+    pub id: i64,
+    pub title: String,
+    pub group_id: i64,
+    pub group_title: String,
+    pub deleted_at: NaiveDateTime,
+    pub purge_on: NaiveDate,
+}
+
+/// Empty anything that has been in the trash longer than `retention` once an
+/// hour, forever
+pub async fn purge_periodically(pool: SqlitePool, retention: TrashRetention) {
+    let mut interval = actix_web::rt::time::interval(Duration::from_secs(60 * 60));
+    loop {
+        interval.tick().await;
+        match queries::purge_trash(&pool, retention).await {
+            Ok(0) => {}
+            Ok(purged) => info!("Purged {} items from the trash", purged),
+            Err(err) => error!("Could not purge the trash: {}", err),
+        }
+    }
+}
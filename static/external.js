@@ -24,3 +24,113 @@ async function startLogin() {
     window.location.replace("/login");
   }
 }
+
+// Log in with a passkey without typing an email first. With autofill the
+// browser offers passkeys in the email field's suggestions instead of a prompt.
+async function startPasskeyLogin(autofill = false) {
+  if (autofill && !(await SimpleWebAuthnBrowser.browserSupportsWebAuthnAutofill())) {
+    return;
+  }
+
+  const res = await fetch("/webauthn/discoverable-login");
+  const loginOptions = await res.json();
+  let loginInfo;
+  try {
+    loginInfo = await SimpleWebAuthnBrowser.startAuthentication(loginOptions.publicKey, autofill);
+  } catch (error) {
+    // Starting another login cancels the autofill one, which isn't a failure
+    if (!autofill) {
+      console.error(error);
+      Alpine.store('notification').show("Auth Failure", "Could not use a passkey, please try again", 'failure');
+    }
+    return;
+  }
+
+  const verificationResp = await fetch("/webauthn/discoverable-login", {
+    method: 'POST',
+    headers: {
+      'Content-Type': 'application/json'
+    },
+    body: JSON.stringify(loginInfo)
+  })
+
+  if (verificationResp.status == 200) {
+    window.location.replace("/dashboard");
+  } else {
+    Alpine.store('notification').show("Auth Failure", "Could not authenticate, please try again", 'failure');
+  }
+}
+
+// Confirm it's still the same person with a passkey before a sensitive change,
+// then go back to where they were
+async function confirmWithPasskey(next) {
+  const res = await fetch("/webauthn/reauth");
+  const authOptions = await res.json();
+  let authInfo;
+  try {
+    authInfo = await SimpleWebAuthnBrowser.startAuthentication(authOptions.publicKey);
+  } catch (error) {
+    console.error(error);
+    Alpine.store('notification').show("Auth Failure", "Could not use a passkey, please try again", 'failure');
+    return;
+  }
+
+  const verificationResp = await fetch("/webauthn/reauth", {
+    method: 'POST',
+    headers: {
+      'Content-Type': 'application/json'
+    },
+    body: JSON.stringify(authInfo)
+  })
+
+  if (verificationResp.ok) {
+    window.location.replace(next);
+  } else {
+    Alpine.store('notification').show("Auth Failure", "Could not confirm it's you, please try again", 'failure');
+  }
+}
+
+document.addEventListener('DOMContentLoaded', () => {
+  if (document.querySelector("input[autocomplete*='webauthn']")) {
+    startPasskeyLogin(true);
+  }
+});
+
+function leadingZeroBits(hash) {
+  let bits = 0;
+  for (const byte of hash) {
+    if (byte === 0) {
+      bits += 8;
+    } else {
+      bits += Math.clz32(byte) - 24;
+      break;
+    }
+  }
+  return bits;
+}
+
+// Find a nonce that hashes with the challenge to enough leading zero bits
+async function solveProofOfWork(challenge, bits) {
+  const encoder = new TextEncoder();
+  for (let nonce = 0; ; nonce++) {
+    const hash = await crypto.subtle.digest('SHA-256', encoder.encode(challenge + nonce));
+    if (leadingZeroBits(new Uint8Array(hash)) >= bits) {
+      return nonce.toString();
+    }
+  }
+}
+
+// Forms that send emails can ask for a proof of work before they're sent
+document.addEventListener('submit', async (event) => {
+  const form = event.target;
+  if (!form.dataset.powChallenge) {
+    return;
+  }
+  event.preventDefault();
+  const button = form.querySelector("button[type='submit']");
+  if (button) {
+    button.disabled = true;
+  }
+  form.elements.pow_nonce.value = await solveProofOfWork(form.dataset.powChallenge, Number(form.dataset.powBits));
+  form.submit();
+});
@@ -7,7 +7,11 @@ function prepareDeleteGoal(groupId, goalId) {
 
       if (res.ok) {
         htmx.ajax('GET', `/groups/${groupId}`, "#main-content")
-        Alpine.store('notification').show('Delete Succeeded', 'Deleted your goal', 'success');
+        Alpine.store('notification').show('Delete Succeeded', 'Moved your goal to the trash', 'success', true, {
+          label: 'Undo',
+          url: '/trash/goals/restore',
+          values: { goals: goalId },
+        });
       } else {
         Alpine.store('notification').show('Delete Failed', 'Could not delete your goal', 'failure');
       }
@@ -41,12 +45,19 @@ function prepareDeleteGroup(groupId) {
 
       if (res.ok) {
         htmx.ajax('GET', '/dashboard', "#main-content");
-        window.location.replace("/dashboard");
-        document.getElementById(`group-nav-link-${groupId}`).remove()
+        window.history.replaceState(null, '', '/dashboard');
+        Alpine.store('location').update();
+        htmx.trigger(document.body, 'groupsChanged');
         Alpine.store('notification').show(
           'Group Deleted',
-          'Your group and all its goals have been deleted.',
-          'success'
+          'Your group and all its goals have been moved to the trash.',
+          'success',
+          true,
+          {
+            label: 'Undo',
+            url: `/trash/groups/${groupId}/restore`,
+            values: {},
+          }
         );
       } else {
         Alpine.store('notification').show(
@@ -79,6 +90,38 @@ function confirmDeleteGroup(element) {
 }
 
 
+async function archiveGroup(element) {
+  const groupId = element.dataset.groupId;
+  try {
+    const res = await fetch(`/groups/${groupId}/archive`, {
+      method: 'PATCH'
+    });
+
+    if (res.ok) {
+      htmx.ajax('GET', '/dashboard', "#main-content");
+      window.history.replaceState(null, '', '/dashboard');
+      Alpine.store('location').update();
+      htmx.trigger(document.body, 'groupsChanged');
+      Alpine.store('notification').show(
+        'Group Archived',
+        'You can find it in the archive whenever you want it back.',
+        'success',
+        true,
+        {
+          label: 'Undo',
+          url: `/archive/groups/${groupId}/restore`,
+          values: {},
+        }
+      );
+    } else {
+      Alpine.store('notification').show('Archive Failed', 'Your group could not be archived', 'failure');
+    }
+  } catch (err) {
+    console.log(err);
+    Alpine.store('notification').show('Archive Failed', 'Your group could not be archived', 'failure');
+  }
+}
+
 async function deleteAccount() {
   try {
     const res = await fetch('/profile/delete', {
@@ -88,9 +131,15 @@ async function deleteAccount() {
       Alpine.store('notification')
         .show(
           'Account Deleted',
-          'Your account has been deleted and your data has been wiped',
+          'Your account is locked and will be wiped soon. Check your email if you change your mind',
         )
       window.location.replace('/');
+    } else if (res.status === 403 && res.headers.has('HX-Redirect')) {
+      // Deleting needs a recent login, so confirm it's them first
+      window.location.assign(res.headers.get('HX-Redirect'));
+    } else if (res.status === 409) {
+      Alpine.store('notification')
+        .show('Delete Failed', await res.text(), 'failure', false);
     } else {
       Alpine.store('notification')
         .show('Delete Failed',
@@ -110,7 +159,7 @@ async function deleteAccount() {
 
 function confirmDeleteAccount() {
   Alpine.store('confirm').show('Delete Your Account',
-    'Are you sure you want to delete your account and all you goals and groups?',
+    'Are you sure you want to delete your account and all you goals and groups? You\'ll get an email with a link to undo it for a little while.',
     'Delete My Account',
     deleteAccount,
   );
@@ -123,6 +172,11 @@ function displayDate(datestring) {
 
 async function updateGoalStage(event, droppedOn) {
   const moving = document.getElementById(event.dataTransfer.getData('text/plain'));
+  draggingGoal = null;
+  if (droppedOn.dataset.stage === moving.dataset.stage) {
+    dropGoalInPlace(moving);
+    return;
+  }
   moving.remove();
   droppedOn.querySelector('.goal-list').prepend(moving);
   const newStage = droppedOn.dataset.stage;
@@ -161,6 +215,47 @@ async function updateGoalStage(event, droppedOn) {
   }
 }
 
+async function rescheduleGoal(event, droppedOn) {
+  const moving = document.getElementById(event.dataTransfer.getData('text/plain'));
+  if (!moving || !moving.id.startsWith('calendar-goal-')) {
+    return;
+  }
+  const oldDay = moving.closest('[data-date]');
+  const newDate = droppedOn.dataset.date;
+  if (oldDay === droppedOn) {
+    return;
+  }
+  moving.remove();
+  droppedOn.querySelector('.goal-list').append(moving);
+
+  const putBack = () => {
+    moving.remove();
+    oldDay.querySelector('.goal-list').append(moving);
+    Alpine.store('notification').show('Update Failed', "Could not reschedule goal", 'failure');
+  }
+
+  try {
+    const res = await fetch(
+      `/groups/${moving.dataset.groupId}/goals/${moving.dataset.goalId}/deadline`,
+      {
+        method: "PATCH",
+        body: new URLSearchParams({ deadline: newDate }),
+      }
+    )
+
+    if (res.ok) {
+      moving.dataset.date = newDate;
+      moving.querySelector('input[name="deadline"]').value = newDate;
+      Alpine.store('notification').show('Goal Rescheduled', `Now due ${displayDate(newDate + 'T00:00')}`);
+    } else {
+      putBack();
+    }
+  } catch (err) {
+    console.log(err);
+    putBack();
+  }
+}
+
 function removeDraggingPlaceholder(stage, id) {
   document.getElementById(`dragging-${stage}-${id}`).remove();
 }
@@ -172,14 +267,88 @@ function createDraggingPlaceholder(stage, id) {
   return div;
 }
 
+let draggingGoal = null;
+
 function startDragging(event, dragging) {
   event.dataTransfer.effectAllowed = 'move';
   event.dataTransfer.setData('text/plain', dragging.id);
+  draggingGoal = dragging;
 }
 
+// With the manual sort the placeholder holds the card's spot so it can be
+// shuffled around the stage, otherwise it shows where the card will land.
 function insertPlaceholder(dragging) {
   const stage = dragging.dataset.stage;
-  document.getElementById(`list-stage-${stage}`).prepend(createDraggingPlaceholder(stage, dragging.id));
+  const list = document.getElementById(`list-stage-${stage}`);
+  const placeholder = createDraggingPlaceholder(stage, dragging.id);
+  if ('manualOrder' in list.dataset) {
+    dragging.before(placeholder);
+  } else {
+    list.prepend(placeholder);
+  }
+}
+
+// Move the placeholder around while a goal is dragged over the other goals in
+// its stage, the same way the groups on the dashboard are shuffled.
+function dragGoalOver(event, over) {
+  if (!draggingGoal || over === draggingGoal || over.dataset.stage !== draggingGoal.dataset.stage) {
+    return;
+  }
+  const placeholder = document.getElementById(`dragging-${draggingGoal.dataset.stage}-${draggingGoal.id}`);
+  if (!placeholder || !('manualOrder' in placeholder.parentNode.dataset)) {
+    return;
+  }
+  const cameFromBefore = over.compareDocumentPosition(placeholder) & Node.DOCUMENT_POSITION_PRECEDING;
+  over.parentNode.insertBefore(placeholder, cameFromBefore ? over.nextSibling : over);
+}
+
+// A goal dropped back in its own stage goes where the placeholder is, and the
+// new order is saved if the stage is sorted by hand.
+function dropGoalInPlace(moving) {
+  const placeholder = document.getElementById(`dragging-${moving.dataset.stage}-${moving.id}`);
+  const list = placeholder.parentNode;
+  if (!('manualOrder' in list.dataset)) {
+    placeholder.remove();
+    return;
+  }
+  placeholder.replaceWith(moving);
+  const goals = Array.from(list.querySelectorAll(':scope > [data-goal-id]'))
+    .map((card) => card.dataset.goalId);
+  htmx.ajax('PATCH', `/groups/${moving.dataset.groupId}/goal-order`, { source: document.body, swap: 'none', values: { goals } });
+}
+
+let draggingGroup = null;
+let groupOrderChanged = false;
+
+function startGroupDrag(event, dragging) {
+  event.dataTransfer.effectAllowed = 'move';
+  event.dataTransfer.setData('text/plain', dragging.dataset.groupId);
+  draggingGroup = dragging;
+  groupOrderChanged = false;
+}
+
+// Shuffle the cards around while a group is dragged over them, it goes after
+// the card if it came from before it and in front of it otherwise.
+function dragGroupOver(event, over) {
+  if (!draggingGroup || over === draggingGroup) {
+    return;
+  }
+  event.dataTransfer.dropEffect = 'move';
+  const cameFromBefore = over.compareDocumentPosition(draggingGroup) & Node.DOCUMENT_POSITION_PRECEDING;
+  over.parentNode.insertBefore(draggingGroup, cameFromBefore ? over.nextSibling : over);
+  groupOrderChanged = true;
+}
+
+function saveGroupOrder() {
+  const changed = groupOrderChanged;
+  draggingGroup = null;
+  groupOrderChanged = false;
+  if (!changed) {
+    return;
+  }
+  const groups = Array.from(document.querySelectorAll('#group-list [data-group-id]'))
+    .map((card) => card.dataset.groupId);
+  htmx.ajax('PATCH', '/groups/order', { source: document.body, swap: 'none', values: { groups } });
 }
 
 
@@ -211,6 +380,48 @@ async function startRegistration() {
 
   if (verificationResponse.ok) {
     Alpine.store('notification').show('Registration Succeeded', 'You can now log in using just this device!', 'success');
+    htmx.trigger(document.body, 'passkeysChanged');
   }
 }
 
+
+document.addEventListener('alpine:init', () => {
+  // Goals picked for a bulk action on the kanban and list views
+  Alpine.store('selection', {
+    active: false,
+    ids: [],
+
+    start() {
+      this.ids = [];
+      this.active = true;
+    },
+
+    stop() {
+      this.ids = [];
+      this.active = false;
+    },
+
+    has(id) {
+      return this.ids.includes(id);
+    },
+
+    toggle(id) {
+      if (this.has(id)) {
+        this.ids = this.ids.filter(i => i !== id);
+      } else {
+        this.ids.push(id);
+      }
+    },
+  });
+
+  // the selected goals may not be on the next page
+  document.addEventListener('htmx:pushedIntoHistory', () => {
+    Alpine.store('selection').stop();
+  });
+  document.addEventListener('htmx:historyRestore', () => {
+    Alpine.store('selection').stop();
+  });
+  document.addEventListener('goalsChanged', () => {
+    Alpine.store('selection').stop();
+  });
+});
@@ -23,28 +23,39 @@ document.addEventListener('alpine:init', () => {
     title: '',
     message: '',
     variant: 'success',
+    action: null,
     closeTimeout: null,
     resetTimeout: null,
 
 
-    show(title, message, variant = 'success', autoHide = true) {
+    show(title, message, variant = 'success', autoHide = true, action = null) {
       if (this.closeTimeout) {
         clearTimeout(this.closeTimeout);
         this.closeTimeout = null;
       }
-      if (this.closeTimeout) {
-        clearTimeout(this.closeTimeout);
-        this.closeTimeout = null;
+      if (this.resetTimeout) {
+        clearTimeout(this.resetTimeout);
+        this.resetTimeout = null;
       }
       this.title = title;
       this.message = message;
       this.open = true;
       this.variant = variant;
+      this.action = action;
 
       if (autoHide) {
-        this.clearTimeout = setTimeout(() => {
+        // leave time to find the undo button
+        this.closeTimeout = setTimeout(() => {
           this.close();
-        }, 4000);
+        }, action ? 8000 : 4000);
+      }
+    },
+
+    runAction() {
+      const action = this.action;
+      this.close();
+      if (action) {
+        htmx.ajax('PATCH', action.url, { source: document.body, swap: 'none', values: action.values });
       }
     },
 
@@ -53,6 +64,7 @@ document.addEventListener('alpine:init', () => {
       this.resetTimeout = setTimeout(() => {
         this.title = '';
         this.message = '';
+        this.action = null;
       }, 300);
     }
   });
@@ -84,6 +96,6 @@ document.addEventListener('alpine:init', () => {
   })
 
   document.addEventListener('notify', event => {
-    Alpine.store('notification').show(event.detail.title, event.detail.message, event.detail.variant, event.detail.autoHide);
+    Alpine.store('notification').show(event.detail.title, event.detail.message, event.detail.variant, event.detail.autoHide, event.detail.action);
   })
 })